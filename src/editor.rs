@@ -1,28 +1,49 @@
 pub mod controllers;
+pub mod error;
+mod keymap;
+pub mod options;
 
 use controllers::Controllers;
-use std::time;
+use error::Result;
+use options::Options;
+use keymap::{Command, KeyChord, Keymap, Lookup};
+use regex::Regex;
 use crossterm::event::*;
 use crossterm::event;
 
 #[macro_export]
 macro_rules! prompt {
-    ($ctrlrs:expr, $($args:tt)*) => {{
+    ($ctrlrs:expr, $args:tt) => {
+        prompt!($ctrlrs, $args, allow_empty = false)
+    };
+    ($ctrlrs:expr, $args:tt, allow_empty = $allow_empty:expr) => {
+        prompt!(
+            $ctrlrs,
+            $args,
+            allow_empty = $allow_empty,
+            callback = |_: &mut Controllers, _: &str, _: KeyCode| {}
+        )
+    };
+    ($ctrlrs:expr, $args:tt, callback = $callback:expr) => {
+        prompt!($ctrlrs, $args, allow_empty = false, callback = $callback)
+    };
+    ($ctrlrs:expr, $args:tt, allow_empty = $allow_empty:expr, callback = $callback:expr) => {{
         let ctrlrs: &mut Controllers = &mut $ctrlrs;
         let mut input = String::with_capacity(32);
+        let mut aborted = false;
         loop {
-            ctrlrs.set_status_msg(format!($($args)*, input));
+            ctrlrs.set_status_msg(format!($args, input));
             ctrlrs.refresh_screen()?;
-            match InputReader.read_key()? {
+            let key_event = InputReader.read_key(ctrlrs)?;
+            match key_event {
                 KeyEvent {
                     code: KeyCode::Enter,
                     modifiers: KeyModifiers::NONE,
                     ..
-                } => {
-                    if !input.is_empty() {
-                        ctrlrs.set_status_msg(String::new());
-                        break;
-                    }
+                } if $allow_empty || !input.is_empty() => {
+                    ctrlrs.set_status_msg(String::new());
+                    $callback(ctrlrs, &input, KeyCode::Enter);
+                    break;
                 },
                 KeyEvent {
                     code: KeyCode::Esc,
@@ -30,7 +51,9 @@ macro_rules! prompt {
                     ..
                 } => {
                     ctrlrs.set_status_msg(String::new());
+                    $callback(ctrlrs, &input, KeyCode::Esc);
                     input.clear();
+                    aborted = true;
                     break;
                 },
                 KeyEvent {
@@ -53,21 +76,52 @@ macro_rules! prompt {
                 },
                 _ => {}
             }
+            $callback(ctrlrs, &input, key_event.code);
         }
-        if input.is_empty() { None } else { Some(input) }
+        if aborted || (input.is_empty() && !$allow_empty) { None } else { Some(input) }
     }};
 }
 
+enum Input {
+    Key(KeyEvent),
+    Paste(String), // bracketed paste, arrives as a whole
+    Mouse(MouseEvent),
+    DiskChanged // the file changed on disk underneath unsaved changes
+}
+
 #[derive(Default)]
 struct InputReader;
 
 impl InputReader {
-    fn read_key(&self) -> crossterm::Result<KeyEvent> {
+    fn read_key(&self, ctrlrs: &mut Controllers) -> Result<KeyEvent> {
         loop {
-            if event::poll(time::Duration::from_millis(5000))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
+            if let Input::Key(event) = self.read_input(ctrlrs, false)? {
+                return Ok(event)
+            }
+        }
+    }
+
+    /// Also reports a change on disk, unless `disk_prompt` is false because a prompt
+    /// is open, in which case it's left for when the prompt is done.
+    fn read_input(&self, ctrlrs: &mut Controllers, disk_prompt: bool) -> Result<Input> {
+        loop {
+            if disk_prompt && ctrlrs.take_disk_prompt() {
+                return Ok(Input::DiskChanged)
+            }
+            if event::poll(ctrlrs.idle_timeout())? {
+                match event::read()? {
+                    Event::Key(event) => return Ok(Input::Key(event)),
+                    Event::Paste(text) => return Ok(Input::Paste(text)),
+                    Event::Mouse(event) => return Ok(Input::Mouse(event)),
+                    Event::FocusGained if ctrlrs.check_disk() => ctrlrs.refresh_screen()?,
+                    Event::Resize(width, height) => {
+                        ctrlrs.resize((width as usize, height as usize));
+                        ctrlrs.refresh_screen()?;
+                    }
+                    _ => {}
                 }
+            } else if ctrlrs.idle() {
+                ctrlrs.refresh_screen()?;
             }
         }
     }
@@ -76,103 +130,430 @@ impl InputReader {
 #[derive(Default)]
 pub struct Editor {
     reader: InputReader,
-    ctrlrs: Controllers
+    ctrlrs: Controllers,
+    keymap: Keymap,
+    pending_keys: Vec<KeyChord>
 }
 
 impl Editor {
-    pub fn new() -> Self {
-        Self { 
+    pub fn new(options: Options) -> Self {
+        let mut ctrlrs = Controllers::new(options);
+        let (keymap, errors) = Keymap::new(ctrlrs.key_overrides());
+        if !errors.is_empty() {
+            ctrlrs.report_startup_error(format!("Key binding error: {}", errors.join(" / ")));
+        }
+        Self {
             reader: InputReader,
-            ctrlrs: Controllers::new()
+            ctrlrs,
+            keymap,
+            pending_keys: Vec::new()
         }
     }
 
-    fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
-            KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: event::KeyModifiers::CONTROL,
-                ..
-            } | KeyEvent {
-                code: KeyCode::Char('ç'),
-                ..
-            } => {
-                if !self.ctrlrs.attempt_to_quit(){
-                    return Ok(true)
-                };
-                return Ok(false)
-            },
-            KeyEvent {
-                code: KeyCode::Char('s'),
-                modifiers: event::KeyModifiers::CONTROL | event::KeyModifiers::ALT,
-                ..
-            } | KeyEvent {
-                code: KeyCode::Char('ß'),
-                ..
-            }=> {
-                if !self.ctrlrs.loaded_from_file() {
+    fn process_keypress(&mut self) -> Result<bool> {
+        let key_event = match self.reader.read_input(&mut self.ctrlrs, true)? {
+            Input::Key(event) => event,
+            Input::Paste(text) => {
+                self.pending_keys.clear();
+                if !self.refuse_readonly() {
+                    self.ctrlrs.insert_paste(&text);
+                }
+                return Ok(true)
+            }
+            Input::Mouse(event) => {
+                self.pending_keys.clear();
+                self.ctrlrs.mouse_event(event);
+                return Ok(true)
+            }
+            Input::DiskChanged => {
+                self.pending_keys.clear();
+                self.resolve_disk_change(false)?;
+                return Ok(true)
+            }
+        };
+        self.pending_keys.push(KeyChord::from(key_event));
+        match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Command(command) => {
+                if self.pending_keys.len() > 1 {
+                    self.ctrlrs.set_status_msg(String::new()); // drop the pending keys hint
+                }
+                self.pending_keys.clear();
+                self.execute(command)
+            }
+            Lookup::Pending => {
+                self.ctrlrs.set_status_msg(format!("{} -", Keymap::describe(&self.pending_keys)));
+                Ok(true)
+            }
+            Lookup::Unbound if self.pending_keys.len() > 1 => {
+                self.ctrlrs.set_status_msg(format!("{} is not bound", Keymap::describe(&self.pending_keys)));
+                self.pending_keys.clear();
+                Ok(true)
+            }
+            Lookup::Unbound => {
+                self.pending_keys.clear();
+                if let KeyEvent {
+                    code: code @ (KeyCode::Char(..) | KeyCode::Tab),
+                    modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
+                    ..
+                } = key_event {
+                    if self.refuse_readonly() {
+                        return Ok(true)
+                    }
+                    self.ctrlrs.insert_char(
+                        match code {
+                            KeyCode::Tab => '\t',
+                            KeyCode::Char(ch) => ch,
+                            _ => unreachable!()
+                        }
+                    )
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn refuse_readonly(&mut self) -> bool {
+        if self.ctrlrs.readonly() {
+            self.ctrlrs.set_status_msg("Opened with --readonly, changes aren't allowed".into());
+        }
+        self.ctrlrs.readonly()
+    }
+
+    fn execute(&mut self, command: Command) -> Result<bool> {
+        if command.edits() && self.refuse_readonly() {
+            return Ok(true)
+        }
+        match command {
+            Command::Quit => return Ok(!self.ctrlrs.attempt_to_quit()),
+            Command::Save => {
+                let save_as = !self.ctrlrs.loaded_from_file();
+                if save_as {
                     let filepath = prompt!(&mut self.ctrlrs, "Save as: {}").map(|it| it.into());
                     if filepath.is_none() {
                         self.ctrlrs.set_status_msg("Save aborted!".into());
                         return Ok(true)
                     }
                     self.ctrlrs.set_filename(filepath);
+                } else {
+                    self.ctrlrs.check_disk();
+                    if self.ctrlrs.has_external_change() && !self.resolve_disk_change(true)? {
+                        return Ok(true)
+                    }
                 }
-                self.ctrlrs.save().map(|len| {
-                    self.ctrlrs.set_status_msg(format!("{} bytes written to disk", len));
-                })?;
-            },
-            KeyEvent {
-                code: key @ (KeyCode::Backspace | KeyCode::Delete),
-                modifiers: shift @ (KeyModifiers::NONE | KeyModifiers::SHIFT),
-                ..
-            } => {
-                if key == KeyCode::Delete {
-                    self.ctrlrs.move_cursor(KeyCode::Right, shift);
-                }
-                self.ctrlrs.delete_char(shift);
-            },
-            KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: event::KeyModifiers::NONE,
-                ..
-            } => self.ctrlrs.insert_newline(),
-            KeyEvent {
-                code: direction @ (
-                    KeyCode::Up
-                    | KeyCode::Down
-                    | KeyCode::Left
-                    | KeyCode::Right
-                    | KeyCode::End
-                    | KeyCode::Home
-                    | KeyCode::PageUp
-                    | KeyCode::PageDown
-                ),
-                modifiers: shift @ (
-                    event::KeyModifiers::NONE 
-                    | event::KeyModifiers::SHIFT 
-                ),
-                ..
-            } => self.ctrlrs.move_cursor(direction, shift),
-            KeyEvent {
-                code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
-                ..
-            } => {
-                self.ctrlrs.insert_char(
-                    match code {
-                        KeyCode::Tab => '\t',
-                        KeyCode::Char(ch) => ch,
-                        _ => unreachable!()
+                match self.ctrlrs.save() {
+                    Ok(len) => self.ctrlrs.set_status_msg(format!("{} bytes written to disk", len)),
+                    Err(err) => {
+                        if save_as {
+                            self.ctrlrs.set_filename(None)
+                        }
+                        self.ctrlrs.set_status_msg(format!("{}, the file on disk is unchanged", err))
                     }
-                )
+                }
+            }
+            Command::Find => self.find()?,
+            Command::Replace => self.replace()?,
+            Command::Undo => self.ctrlrs.undo(),
+            Command::Redo => self.ctrlrs.redo(),
+            Command::ToggleWrapMode => self.ctrlrs.toggle_wrap_mode(),
+            Command::ToggleCentered => self.ctrlrs.toggle_centered(),
+            Command::SetTextWidth => {
+                if let Some(width) = prompt!(&mut self.ctrlrs, "Text width (columns or 'full'): {}") {
+                    self.ctrlrs.set_text_width(&width)
+                }
+            }
+            Command::SetWrapColumn => {
+                if let Some(column) = prompt!(&mut self.ctrlrs, "Wrap column (columns or 'width'): {}") {
+                    self.ctrlrs.set_wrap_column(&column)
+                }
+            }
+            Command::SetEncoding => {
+                if let Some(name) = prompt!(&mut self.ctrlrs, "Encoding (utf-8, utf-8-bom, utf-16le, utf-16be, latin-1): {}") {
+                    self.ctrlrs.set_encoding(&name)
+                }
+            }
+            Command::SetLineEnding => {
+                if let Some(name) = prompt!(&mut self.ctrlrs, "Line endings (lf, crlf, cr): {}") {
+                    self.ctrlrs.set_line_ending(&name)
+                }
+            }
+            Command::ToggleFinalNewline => self.ctrlrs.toggle_final_newline(),
+            Command::CursorUp
+            | Command::CursorDown
+            | Command::CursorLeft
+            | Command::CursorRight
+            | Command::JumpUp
+            | Command::JumpDown
+            | Command::WordLeft
+            | Command::WordRight
+            | Command::LineStart
+            | Command::LineEnd
+            | Command::PageUp
+            | Command::PageDown => {
+                self.ctrlrs.set_selecting(false);
+                self.move_cursor(command)
+            }
+            Command::SelectUp => self.select(Command::CursorUp),
+            Command::SelectDown => self.select(Command::CursorDown),
+            Command::SelectLeft => self.select(Command::CursorLeft),
+            Command::SelectRight => self.select(Command::CursorRight),
+            Command::SelectWordLeft => self.select(Command::WordLeft),
+            Command::SelectWordRight => self.select(Command::WordRight),
+            Command::SelectLineStart => self.select(Command::LineStart),
+            Command::SelectLineEnd => self.select(Command::LineEnd),
+            Command::SelectPageUp => self.select(Command::PageUp),
+            Command::SelectPageDown => self.select(Command::PageDown),
+            Command::SelectAll => self.ctrlrs.select_all(),
+            Command::Copy => self.ctrlrs.copy(),
+            Command::Cut => self.ctrlrs.cut(),
+            Command::Paste => self.ctrlrs.paste(),
+            Command::OpenFile => self.open_file()?,
+            Command::NextBuffer => self.ctrlrs.cycle_buffer(true),
+            Command::PrevBuffer => self.ctrlrs.cycle_buffer(false),
+            Command::ListBuffers => self.list_buffers()?,
+            Command::SplitHorizontal => self.ctrlrs.split_pane(false),
+            Command::SplitVertical => self.ctrlrs.split_pane(true),
+            Command::ClosePane => self.ctrlrs.close_pane(),
+            Command::NextPane => self.ctrlrs.cycle_pane(true),
+            Command::PrevPane => self.ctrlrs.cycle_pane(false),
+            Command::GrowPane => self.ctrlrs.resize_pane(5),
+            Command::ShrinkPane => self.ctrlrs.resize_pane(-5),
+            Command::DeleteBack
+            | Command::DeleteForward
+            | Command::DeleteWordBack
+            | Command::DeleteWordForward if self.ctrlrs.delete_selection() => {}
+            Command::DeleteBack => self.ctrlrs.delete_char(KeyModifiers::NONE),
+            Command::DeleteForward => {
+                self.ctrlrs.move_cursor(KeyCode::Right, KeyModifiers::NONE);
+                self.ctrlrs.delete_char(KeyModifiers::NONE)
+            }
+            Command::DeleteWordBack => self.ctrlrs.delete_char(KeyModifiers::SHIFT),
+            Command::DeleteWordForward => {
+                self.ctrlrs.move_cursor(KeyCode::Right, KeyModifiers::SHIFT);
+                self.ctrlrs.delete_char(KeyModifiers::SHIFT)
             }
-            _ => {}
+            Command::Newline => self.ctrlrs.insert_newline(),
+            Command::Palette => return self.command_palette()
         }
         Ok(true)
     }
 
-    pub fn run(&mut self) -> crossterm::Result<bool> {
+    fn select(&mut self, movement: Command) {
+        self.ctrlrs.set_selecting(true);
+        self.move_cursor(movement)
+    }
+
+    fn move_cursor(&mut self, movement: Command) {
+        let (key, modifiers) = match movement {
+            Command::CursorUp => (KeyCode::Up, KeyModifiers::NONE),
+            Command::CursorDown => (KeyCode::Down, KeyModifiers::NONE),
+            Command::CursorLeft => (KeyCode::Left, KeyModifiers::NONE),
+            Command::CursorRight => (KeyCode::Right, KeyModifiers::NONE),
+            Command::JumpUp => (KeyCode::Up, KeyModifiers::SHIFT),
+            Command::JumpDown => (KeyCode::Down, KeyModifiers::SHIFT),
+            Command::WordLeft => (KeyCode::Left, KeyModifiers::SHIFT),
+            Command::WordRight => (KeyCode::Right, KeyModifiers::SHIFT),
+            Command::LineStart => (KeyCode::Home, KeyModifiers::NONE),
+            Command::LineEnd => (KeyCode::End, KeyModifiers::NONE),
+            Command::PageUp => (KeyCode::PageUp, KeyModifiers::NONE),
+            Command::PageDown => (KeyCode::PageDown, KeyModifiers::NONE),
+            _ => return
+        };
+        self.ctrlrs.move_cursor(key, modifiers)
+    }
+
+    fn command_palette(&mut self) -> Result<bool> {
+        let commands: Vec<Command> = Command::all().filter(|cmd| *cmd != Command::Palette).collect();
+        self.ctrlrs.open_picker(
+            commands
+                .iter()
+                .map(|cmd| (cmd.description().to_string(), self.keymap.bindings_for(*cmd)))
+                .collect()
+        );
+        let chosen = prompt!(
+            &mut self.ctrlrs,
+            "Command: {} (Use ESC / Arrows / Enter)",
+            allow_empty = true,
+            callback = Controllers::picker_callback
+        );
+        let selected = self.ctrlrs.close_picker();
+        match (chosen, selected) {
+            (Some(_), Some(idx)) => self.execute(commands[idx]),
+            _ => Ok(true)
+        }
+    }
+
+    fn open_file(&mut self) -> Result<()> {
+        self.ctrlrs.open_file_picker();
+        let typed = prompt!(
+            &mut self.ctrlrs,
+            "Open: {} (Use ESC / Arrows / Enter, a path that doesn't match opens as typed)",
+            allow_empty = true,
+            callback = Controllers::picker_callback
+        );
+        let path = self.ctrlrs.close_file_picker(typed.as_deref().unwrap_or_default());
+        if let (Some(_), Some(path)) = (typed, path) {
+            self.ctrlrs.open_file(path.into())
+        }
+        Ok(())
+    }
+
+    fn list_buffers(&mut self) -> Result<()> {
+        self.ctrlrs.open_picker(self.ctrlrs.buffer_list());
+        let chosen = prompt!(
+            &mut self.ctrlrs,
+            "Buffer: {} (Use ESC / Arrows / Enter)",
+            allow_empty = true,
+            callback = Controllers::picker_callback
+        );
+        if let (Some(_), Some(idx)) = (chosen, self.ctrlrs.close_picker()) {
+            self.ctrlrs.switch_buffer(idx)
+        }
+        Ok(())
+    }
+
+    fn find(&mut self) -> Result<()> {
+        self.ctrlrs.start_search();
+        prompt!(
+            &mut self.ctrlrs,
+            "Search: {} (Use ESC / Arrows / Enter)",
+            callback = Controllers::find_callback
+        );
+        Ok(())
+    }
+
+    fn replace(&mut self) -> Result<()> {
+        let Some(pattern) = (if self.ctrlrs.has_selection() {
+            prompt!(&mut self.ctrlrs, "Replace in selection (regex): {}")
+        } else {
+            prompt!(&mut self.ctrlrs, "Replace (regex): {}")
+        }) else {
+            return Ok(())
+        };
+        let regex = match Regex::new(&pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.ctrlrs.set_status_msg(format!("Invalid regex: {}", err));
+                return Ok(())
+            }
+        };
+        let Some(template) = prompt!(&mut self.ctrlrs, "Replace with ($1 for captures): {}", allow_empty = true) else {
+            return Ok(())
+        };
+        self.ctrlrs.start_replace(regex, template);
+        let mut replace_all = false;
+        while self.ctrlrs.next_replace_match() {
+            if !replace_all {
+                self.ctrlrs.set_status_msg("Replace this match? (y = yes / n = no / a = all / q = quit)".into());
+                self.ctrlrs.refresh_screen()?;
+                let answer = loop {
+                    match self.reader.read_key(&mut self.ctrlrs)?.code {
+                        KeyCode::Char(answer @ ('y' | 'n' | 'a' | 'q')) => break answer,
+                        KeyCode::Esc => break 'q',
+                        _ => {}
+                    }
+                };
+                match answer {
+                    'n' => continue,
+                    'a' => replace_all = true,
+                    'q' => break,
+                    _ => {}
+                }
+            }
+            self.ctrlrs.replace_match()
+        }
+        let replaced = self.ctrlrs.finish_replace();
+        self.ctrlrs.set_status_msg(format!("Replaced {} occurrence(s)", replaced));
+        Ok(())
+    }
+
+    /// Asks what to do about a newer version of the file on disk, returning
+    /// whether a save that's waiting on the answer should go ahead.
+    fn resolve_disk_change(&mut self, saving: bool) -> Result<bool> {
+        let name = self.ctrlrs.buffer_name();
+        self.ctrlrs.set_status_msg(format!(
+            "{} changed on disk: r = reload / k = keep yours{} / m = merge / Esc = {}",
+            name,
+            if saving { " and save" } else { "" },
+            if saving { "cancel save" } else { "decide later" }
+        ));
+        self.ctrlrs.refresh_screen()?;
+        loop {
+            match self.reader.read_key(&mut self.ctrlrs)?.code {
+                KeyCode::Char('r') => {
+                    self.ctrlrs.reload_from_disk();
+                    self.ctrlrs.set_status_msg(format!("Reloaded {}, Ctrl+Z brings your changes back", name));
+                    return Ok(false)
+                }
+                KeyCode::Char('k') => {
+                    self.ctrlrs.keep_yours();
+                    self.ctrlrs.set_status_msg(format!("Keeping your version of {}", name));
+                    return Ok(true)
+                }
+                KeyCode::Char('m') => {
+                    let message = match self.ctrlrs.merge_from_disk() {
+                        0 => format!("Merged the changes on disk into {}, save to keep them", name),
+                        conflicts => format!("Merged {} with {} conflict(s), look for <<<<<<< markers", name, conflicts)
+                    };
+                    self.ctrlrs.set_status_msg(message);
+                    return Ok(false)
+                }
+                KeyCode::Esc => {
+                    self.ctrlrs.set_status_msg(if saving { "Save aborted!".into() } else { String::new() });
+                    return Ok(false)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn offer_recovery(&mut self, idx: usize) -> Result<()> {
+        self.ctrlrs.switch_buffer(idx);
+        let name = self.ctrlrs.buffer_name();
+        let summary = match self.ctrlrs.swap_summary() {
+            Ok(summary) => summary,
+            Err(err) => {
+                self.ctrlrs.defer_swap();
+                self.ctrlrs.set_status_msg(err.to_string());
+                return Ok(())
+            }
+        };
+        loop {
+            self.ctrlrs.set_status_msg(format!(
+                "Swap file found for {}: r = recover / d = discard / c = compare / Esc = decide later ({})",
+                name,
+                summary
+            ));
+            self.ctrlrs.refresh_screen()?;
+            let result = match self.reader.read_key(&mut self.ctrlrs)?.code {
+                KeyCode::Char('r') => self.ctrlrs
+                    .recover_swap()
+                    .map(|_| format!("Recovered {} from its swap file, save to keep the changes", name)),
+                KeyCode::Char('d') => self.ctrlrs.discard_swap().map(|_| format!("Discarded the swap file of {}", name)),
+                KeyCode::Char('c') => match self.ctrlrs.compare_swap() {
+                    Ok(()) => continue,
+                    Err(err) => Err(err)
+                },
+                KeyCode::Esc => {
+                    self.ctrlrs.defer_swap();
+                    Ok(format!("Kept the swap file of {}, it won't be overwritten", name))
+                }
+                _ => continue
+            };
+            match result {
+                Ok(message) => self.ctrlrs.set_status_msg(message),
+                Err(err) => {
+                    self.ctrlrs.defer_swap();
+                    self.ctrlrs.set_status_msg(err.to_string())
+                }
+            }
+            return Ok(())
+        }
+    }
+
+    pub fn run(&mut self) -> Result<bool> {
+        while let Some(idx) = self.ctrlrs.next_recovery() {
+            self.offer_recovery(idx)?;
+        }
         self.ctrlrs.refresh_screen()?;
         self.process_keypress()
     }
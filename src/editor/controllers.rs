@@ -1,74 +1,190 @@
 mod position;
 mod file;
 mod writing;
+mod history;
+mod buffer;
+mod highlight;
+mod search;
+mod replace;
+mod config;
+mod picker;
+mod clipboard;
+mod document;
+mod pane;
+mod swap;
+mod merge;
+mod encoding;
 
-use position::CursorController;
+use position::{CursorController, WrapMode};
+use history::{Edit, EditKind, HistoryController};
 use writing::{StatusMessage, WritingController};
-use file::{FileController, Row};
-use std::{cmp, io};
+use file::FileController;
+use highlight::{Highlight, SyntaxController};
+use search::SearchController;
+use replace::ReplaceController;
+use regex::Regex;
+use config::Config;
+use crate::editor::error::{self, EditorError};
+use crate::editor::options::{Options, Source};
+use encoding::FileFormat;
+use picker::Picker;
+use clipboard::Clipboard;
+use document::Document;
+use pane::{PaneController, Rect};
+use swap::SwapState;
+use std::{cmp, env, io, mem, time};
 use std::io::Write;
 use crossterm::event::*;
 use crossterm::{cursor, queue, style, terminal};
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const MAX_QUIT_ATTEMPTS: u64 = 3;
+const DOUBLE_CLICK: time::Duration = time::Duration::from_millis(400);
+const MAX_PICKER_FILES: usize = 20_000;
+const MARKDOWN_BONUS: i64 = 150; // about one and a half matched characters
 
 #[derive(Default)]
 pub struct Controllers {
     writing_ctrlr: WritingController,
     cursor_ctrlr: CursorController,
     file_ctrlr: FileController,
+    history: HistoryController,
+    syntax: SyntaxController,
+    search: SearchController,
+    replace: Option<ReplaceController>,
+    picker: Option<Picker>,
+    register: String, // last copied or cut text, used when the clipboard can't be read
+    clipboard: Clipboard,
+    last_click: Option<(time::Instant, (usize, usize))>, // to recognise double clicks
     status_msg: StatusMessage,
+    config: Config,
     dirty: u64,
+    buffers: Vec<Document>, // every open file, the entry at `current` is a placeholder while it's active
+    current: usize,
+    panes: PaneController,
+    screen: (usize, usize), // terminal size
     quit_attempts: u64,
+    clean_exit: bool, // swap files are only removed when the user quit
+    disk_prompt: bool, // the active file changed on disk and the user should decide what to keep
+    readonly: bool
 }
 
 impl Drop for Controllers {
     fn drop(&mut self) {
-        if self.dirty > 0 && self.quit_attempts < MAX_QUIT_ATTEMPTS { // if dirty and not intentionally quit, save a .tmp file
-            match &self.file_ctrlr.filename {
-                Some(name) => {
-                    let mut new_filename = name.clone();
-                    new_filename.set_extension("tmp");
-                    self.file_ctrlr
-                        .save_file(&new_filename)
-                        .expect("Failed to save emergency .tmp file on crash");
-                },
-                None => {
-                    self.file_ctrlr
-                        .save_file(&PathBuf::from("./tm-crashed.tmp"))
-                        .expect("Failed to save emergency .tmp file on crash");
-                }
+        let clean_exit = self.clean_exit;
+        for (file_ctrlr, dirty) in self.documents_mut() {
+            if !clean_exit { // crashing, so keep the latest changes for the next session
+                let _ = file_ctrlr.update_swap(dirty);
+            } else if matches!(file_ctrlr.swap, SwapState::Written(_) | SwapState::Failed(_)) {
+                let _ = file_ctrlr.remove_swap();
             }
         }
     }
 }
 
 impl Controllers {
-    pub fn new() -> Self {
-        let win_size = terminal::size()
-            .map(|(x,y)| (x as usize, y as usize - 2))
-            .unwrap();
-        Self {
+    pub fn new(options: Options) -> Self {
+        Self::with_screen(options, terminal::size)
+    }
+
+    /// Like `new`, with the terminal size read from `screen_size` so it can be faked.
+    pub fn with_screen(options: Options, screen_size: fn() -> io::Result<(u16, u16)>) -> Self {
+        let screen = screen_size()
+            .map_or((80, 24), |(x, y)| (x as usize, y as usize)); // not a terminal, the first resize fixes it
+        let win_size = (screen.0, screen.1.saturating_sub(2));
+        let (config, errors) = Config::load(options.config.as_deref());
+        let mut cursor_ctrlr = CursorController::new(win_size);
+        cursor_ctrlr.text_width = config.text_width;
+        cursor_ctrlr.wrap_column = config.wrap_column;
+        cursor_ctrlr.centered = config.centered;
+        cursor_ctrlr.wrap_mode = config.wrap_mode;
+        cursor_ctrlr.update_layout();
+        let mut buffers = Vec::new();
+        let mut open_errors = Vec::new();
+        for file in options.files {
+            let file_ctrlr = match file.source {
+                Source::Path(path) => match FileController::open(path.clone(), config.tab_stop) {
+                    Ok(file_ctrlr) => file_ctrlr,
+                    Err(err) => {
+                        open_errors.push(EditorError::file("open", Some(&path), err).to_string());
+                        continue
+                    }
+                },
+                Source::Stdin(bytes) => FileController::from_bytes(&bytes, config.tab_stop)
+            };
+            let mut document = Document::new(file_ctrlr);
+            if let Some((line, column)) = file.position { // clamped once the buffer is shown
+                document.view.cursor = (column.saturating_sub(1), line.saturating_sub(1));
+            }
+            buffers.push(document);
+        }
+        if buffers.is_empty() {
+            let mut file_ctrlr = FileController::new(config.tab_stop);
+            file_ctrlr.swap = SwapState::detect(None);
+            buffers.push(Document::new(file_ctrlr));
+        }
+        if !errors.is_empty() {
+            open_errors.insert(0, format!("Config error: {}", errors.join(" / ")));
+        }
+        let initial_message = if open_errors.is_empty() { config.help_text.clone() } else { open_errors.join(" / ") };
+        let mut ctrlrs = Self {
             writing_ctrlr: WritingController::new(),
-            cursor_ctrlr: CursorController::new(win_size),
-            file_ctrlr: FileController::new(),
-            status_msg: StatusMessage::default(),
+            cursor_ctrlr,
+            file_ctrlr: FileController::new(config.tab_stop),
+            history: HistoryController::new(),
+            syntax: SyntaxController::default(),
+            search: SearchController::default(),
+            replace: None,
+            picker: None,
+            register: String::new(),
+            clipboard: config.clipboard.clone().resolve(),
+            last_click: None,
+            status_msg: StatusMessage::new(initial_message, config.message_timeout),
+            config,
             dirty: 0,
+            buffers,
+            current: 0,
+            panes: PaneController::new(0),
+            screen,
             quit_attempts: 0,
-        }
+            clean_exit: false,
+            disk_prompt: false,
+            readonly: options.readonly
+        };
+        ctrlrs.swap_document(0);
+        ctrlrs.cursor_ctrlr.clamp(&ctrlrs.file_ctrlr);
+        ctrlrs
     }
     
-    fn draw_rows(&mut self) -> io::Result<()> {
-        for i in 0..self.cursor_ctrlr.editor_height {
-            let file_row = self.cursor_ctrlr.row_offset + i;
+    fn draw_rows(&mut self, area: Rect, focused: bool) -> io::Result<()> {
+        let overlay = self.picker
+            .as_ref()
+            .filter(|_| focused)
+            .map(|picker| picker.lines(self.cursor_ctrlr.editor_width))
+            .unwrap_or_default();
+        let text_height = self.cursor_ctrlr.editor_height.saturating_sub(overlay.len());
+        let mut file_row = self.cursor_ctrlr.row_offset;
+        let mut segment = self.cursor_ctrlr.segment_offset;
+        let mut i = 0;
+        if let Some(preview) = self.picker.as_ref().filter(|_| focused).and_then(|picker| picker.preview.clone()) {
+            for (idx, line) in preview.iter().chain(std::iter::repeat(&String::new())).take(text_height).enumerate() {
+                self.begin_line(area, idx)?;
+                let render = FileController::render_row(line, self.config.tab_stop).concat();
+                self.writing_ctrlr.push_str(&FileController::render_slice(&render, 0, self.cursor_ctrlr.editor_width));
+            }
+            i = text_height;
+        }
+        while i < text_height {
+            self.begin_line(area, i)?;
             if file_row >= self.file_ctrlr.count_rows() {
                 if self.file_ctrlr.filename.is_none() && self.dirty < 10
                     && i == self.cursor_ctrlr.editor_height / 4 {
                     let welcome = format!("Text Magic editor -- Version {}", VERSION);
                     let len = cmp::min(welcome.len(), self.cursor_ctrlr.editor_width);
-                    let mut padding = (self.cursor_ctrlr.editor_width - welcome.len()) / 2;
+                    let mut padding = self.cursor_ctrlr.editor_width.saturating_sub(welcome.len()) / 2;
                     if padding != 0 {
                         self.writing_ctrlr.push('~');
                         padding -= 1;
@@ -78,39 +194,135 @@ impl Controllers {
                 } else {
                     self.writing_ctrlr.push('~');
                 }
-            } else {
-                let row = self.file_ctrlr.get_render(file_row);
-                let column_offset = self.cursor_ctrlr.column_offset;
-                let len = cmp::min(row.len().saturating_sub(column_offset), self.cursor_ctrlr.editor_width);
-                let start = if len == 0 { 0 } else { column_offset };
-                self.writing_ctrlr.push_str(&row[start..start+len])
+                i += 1;
+                continue
             }
-            queue!(
-                self.writing_ctrlr,
-                terminal::Clear(terminal::ClearType::UntilNewLine)
-            )?;
-            self.writing_ctrlr.push_str("\r\n")
+            let cells = self.file_ctrlr.get_render(file_row);
+            let highlights = self.row_highlights(file_row, cells.len());
+            let segments = match self.cursor_ctrlr.wrap_mode {
+                WrapMode::Soft => CursorController::wrap_segments(&cells, self.cursor_ctrlr.wrap_width()),
+                WrapMode::Hard => vec![0]
+            };
+            for (idx, start) in segments.iter().enumerate().skip(segment) {
+                if i >= text_height {
+                    break
+                }
+                let end = segments.get(idx + 1).copied().unwrap_or(cells.len());
+                if idx > segment {
+                    self.begin_line(area, i)?;
+                }
+                self.draw_row(&cells[*start..end], &highlights[*start..end])?;
+                i += 1;
+            }
+            segment = 0;
+            file_row += 1;
         }
+        for (line, selected) in overlay {
+            self.begin_line(area, i)?;
+            i += 1;
+            if selected {
+                self.writing_ctrlr.push_str(&style::Attribute::Reverse.to_string());
+            }
+            self.writing_ctrlr.push_str(&line);
+            self.writing_ctrlr.push_str(&style::Attribute::Reset.to_string());
+        }
+        Ok(())
+    }
+
+    fn draw_margin(&mut self) {
+        (0..self.cursor_ctrlr.left_margin).for_each(|_| self.writing_ctrlr.push(' '))
+    }
+
+    fn begin_line(&mut self, area: Rect, line: usize) -> io::Result<()> { // clears the line within the pane
+        queue!(self.writing_ctrlr, cursor::MoveTo(area.x as u16, (area.y + line) as u16))?;
+        if area.x + area.width >= self.screen.0 {
+            queue!(self.writing_ctrlr, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        } else {
+            self.writing_ctrlr.push_str(&format!("\x1b[{}X", area.width)); // erase characters, keeps the cursor
+        }
+        self.draw_margin();
         Ok(())
     }
 
-    fn draw_status_bar(&mut self) {
+    fn row_highlights(&self, file_row: usize, len: usize) -> Vec<Highlight> {
+        let row = self.file_ctrlr.get_editor_row(file_row);
+        let mut highlights = self.syntax.highlights(file_row);
+        highlights.resize(len, Highlight::Normal);
+        self.search.highlight_matches(&row, &mut highlights);
+        if let Some((_, start_byte, end_byte)) = self.replace
+            .as_ref()
+            .and_then(|replace| replace.current)
+            .filter(|(row_idx, ..)| *row_idx == file_row) {
+            let cells = FileController::grapheme_range(&row, start_byte..end_byte);
+            Self::fill_cells(&mut highlights, cells, Highlight::SearchMatch)
+        }
+        if let Some(((x1, y1), (x2, y2))) = self.selection_range() {
+            if (y1..=y2).contains(&file_row) {
+                let start = if file_row == y1 { x1 } else { 0 };
+                let end = if file_row == y2 { x2 } else { len };
+                Self::fill_cells(&mut highlights, start..end, Highlight::Selection)
+            }
+        }
+        highlights
+    }
+
+    fn fill_cells(highlights: &mut [Highlight], cells: Range<usize>, highlight: Highlight) {
+        let end = cmp::min(cells.end, highlights.len());
+        highlights[cmp::min(cells.start, end)..end].fill(highlight)
+    }
+
+    fn draw_row(&mut self, render: &[String], highlights: &[Highlight]) -> io::Result<()> {
+        let start = self.cursor_ctrlr.column_offset;
+        let end = start + self.cursor_ctrlr.editor_width;
+        let mut col = 0;
+        let mut current = Highlight::Normal;
+        for (cell, hl) in render.iter().zip(highlights) {
+            let cell_end = col + cell.width();
+            if col >= end {
+                break
+            }
+            if cell_end > start {
+                if *hl != current {
+                    let (fg, bg) = hl.colors();
+                    queue!(self.writing_ctrlr, style::SetColors(style::Colors::new(fg, bg)))?;
+                    current = *hl;
+                }
+                if col >= start && cell_end <= end {
+                    self.writing_ctrlr.push_str(cell)
+                } else { // wide character cut by the edge of the screen
+                    (cmp::max(col, start)..cmp::min(cell_end, end)).for_each(|_| self.writing_ctrlr.push(' '))
+                }
+            }
+            col = cell_end;
+        }
+        queue!(self.writing_ctrlr, style::ResetColor)
+    }
+
+    fn draw_status_bar(&mut self, area: Rect, focused: bool) -> io::Result<()> {
+        self.begin_line(area, area.height - 1)?;
         self.writing_ctrlr.push_str(&style::Attribute::Reverse.to_string());
+        if focused && self.panes.panes.len() > 1 {
+            self.writing_ctrlr.push_str(&style::Attribute::Bold.to_string());
+        }
         let info = format!(
-            "{} {} -- {} lines",
+            "{}{} {} -- {} lines{}",
+            if self.buffers.len() > 1 { format!("[{}/{}] ", self.current + 1, self.buffers.len()) } else { String::new() },
             self.file_ctrlr
                 .filename
                 .as_ref()
                 .and_then(|path| path.file_name())
                 .and_then(|name| name.to_str())
                 .unwrap_or("[No Name]"),
-            if self.dirty > 0 { "(modified)" } else { "" },
-            self.file_ctrlr.count_rows()
+            if self.dirty > 0 { "(modified)" } else if self.readonly { "(read-only)" } else { "" },
+            self.file_ctrlr.count_rows(),
+            if self.search.wrapped { " -- search wrapped around" } else { "" }
         );
-        let info_len = cmp::min(info.len(), self.cursor_ctrlr.editor_width);
-        self.writing_ctrlr.push_str(&info[..info_len]);
+        let info = FileController::render_slice(&info, 0, self.cursor_ctrlr.editor_width);
+        let info_len = info.width();
+        self.writing_ctrlr.push_str(&info);
         let line_info = format!(
-            "{}/{}",
+            "{}  {}/{}",
+            self.file_ctrlr.format,
             self.cursor_ctrlr.cursor_y + 1,
             self.file_ctrlr.count_rows()
         );
@@ -123,104 +335,183 @@ impl Controllers {
             }
         }
         self.writing_ctrlr.push_str(&style::Attribute::Reset.to_string());
-        self.writing_ctrlr.push_str("\r\n");
+        Ok(())
     }
 
-    fn draw_message_bar(&mut self) {
+    fn draw_message_bar(&mut self) -> io::Result<()> {
         queue!(
             self.writing_ctrlr,
+            cursor::MoveTo(0, self.screen.1.saturating_sub(1) as u16),
             terminal::Clear(terminal::ClearType::UntilNewLine)
-        ).unwrap();
+        )?;
+        let width = if self.panes.panes.len() > 1 {
+            self.screen.0
+        } else {
+            self.draw_margin();
+            self.cursor_ctrlr.editor_width
+        };
         if let Some(msg) = self.status_msg.message() {
-            let len = cmp::min(self.cursor_ctrlr.editor_width, msg.len());
-            self.writing_ctrlr.push_str(&msg[..len])
+            self.writing_ctrlr.push_str(&FileController::render_slice(msg, 0, width))
         }
+        Ok(())
+    }
+
+    fn draw_pane(&mut self, area: Rect, focused: bool) -> io::Result<bool> { // false if it's too small to show
+        if area.height < 2 || area.width == 0 {
+            return Ok(false)
+        }
+        self.cursor_ctrlr.resize((area.width, area.height - 1));
+        self.cursor_ctrlr.scroll(&self.file_ctrlr);
+        self.syntax.update(
+            &mut self.file_ctrlr,
+            self.cursor_ctrlr.row_offset + self.cursor_ctrlr.editor_height
+        );
+        self.draw_rows(area, focused)?;
+        self.draw_status_bar(area, focused)?;
+        Ok(true)
+    }
+
+    fn draw_inactive_pane(&mut self, idx: usize) -> io::Result<()> {
+        let (buffer, view, area) = {
+            let pane = &self.panes.panes[idx];
+            (pane.buffer, pane.view, pane.area)
+        };
+        let active_view = self.cursor_ctrlr.view();
+        let active = self.current;
+        let replace = self.replace.take_if(|_| buffer != active); // its match is in the active buffer's text
+        if buffer != active {
+            self.swap_document(active);
+            self.swap_document(buffer);
+            self.current = buffer;
+        }
+        let remembered = self.cursor_ctrlr.view();
+        self.cursor_ctrlr.set_view(view);
+        self.cursor_ctrlr.clamp(&self.file_ctrlr);
+        let result = self.draw_pane(area, false);
+        self.panes.panes[idx].view = self.cursor_ctrlr.view();
+        self.cursor_ctrlr.set_view(remembered);
+        if buffer != active {
+            self.swap_document(buffer);
+            self.swap_document(active);
+            self.current = active;
+        }
+        if replace.is_some() {
+            self.replace = replace
+        }
+        self.cursor_ctrlr.set_view(active_view);
+        result.map(|_| ())
     }
     
     pub fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        self.cursor_ctrlr.scroll(&self.file_ctrlr);
-        let (mut x, mut y) = self.cursor_ctrlr.pos();
-        x -= self.cursor_ctrlr.column_offset;
-        y -= self.cursor_ctrlr.row_offset;
-        queue!(
-            self.writing_ctrlr,
-            cursor::Hide,
-            cursor::MoveTo(0, 0)
-        )?;
-        self.draw_rows()?;
-        self.draw_status_bar();
-        self.draw_message_bar();
-        queue!(
-            self.writing_ctrlr, 
-            cursor::MoveTo(x as u16, y as u16),
-            cursor::Show
-        )?;
+        self.panes.arrange(Rect { x: 0, y: 0, width: self.screen.0, height: self.screen.1.saturating_sub(1) });
+        queue!(self.writing_ctrlr, cursor::Hide)?;
+        for idx in 0..self.panes.panes.len() {
+            if idx != self.panes.active {
+                self.draw_inactive_pane(idx)?;
+            }
+        }
+        let area = self.panes.panes[self.panes.active].area;
+        let shown = self.draw_pane(area, true)?;
+        for separator in self.panes.separators.clone() {
+            for y in separator.y..separator.y + separator.height {
+                queue!(self.writing_ctrlr, cursor::MoveTo(separator.x as u16, y as u16))?;
+                self.writing_ctrlr.push('│');
+            }
+        }
+        self.draw_message_bar()?;
+        if let Some((x, y)) = self.cursor_ctrlr.pos().filter(|_| shown) {
+            queue!(
+                self.writing_ctrlr,
+                cursor::MoveTo((area.x + x) as u16, (area.y + y) as u16),
+                cursor::Show
+            )?;
+        }
         self.writing_ctrlr.flush()
     }
 
+    fn record_edit<F: FnOnce(&mut Self)>(&mut self, row: usize, count: usize, kind: EditKind, edit: F) {
+        let rows_before = self.file_ctrlr.count_rows();
+        let count = cmp::min(count, rows_before.saturating_sub(row));
+        let old_rows = self.file_ctrlr.row_contents(row, count);
+        let cursor_before = (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y);
+        edit(self);
+        self.cursor_ctrlr.anchor = None;
+        let new_count = (count + self.file_ctrlr.count_rows()).saturating_sub(rows_before);
+        self.push_edit(row, old_rows, new_count, cursor_before, kind)
+    }
+
+    fn push_edit(&mut self, row: usize, old_rows: Vec<String>, new_count: usize, cursor_before: (usize, usize), kind: EditKind) {
+        let new_rows = self.file_ctrlr.row_contents(row, new_count);
+        if old_rows == new_rows { // nothing changed, don't clutter the history
+            return
+        }
+        self.history.record(Edit {
+            row,
+            old_rows,
+            new_rows,
+            cursor_before,
+            cursor_after: (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y),
+            kind
+        });
+        self.dirty += 1;
+    }
+
     pub fn insert_char(&mut self, ch: char) {
+        self.delete_selection();
+        self.record_edit(self.cursor_ctrlr.cursor_y, 1, EditKind::Insert, |ctrlrs| ctrlrs.write_char(ch))
+    }
+
+    fn write_char(&mut self, ch: char) {
         if self.cursor_ctrlr.cursor_y == self.file_ctrlr.count_rows() {
             self.file_ctrlr.insert_row(
                 self.cursor_ctrlr.cursor_y,
                 String::new()
             );
-            self.file_ctrlr
-                .get_editor_row_mut(self.cursor_ctrlr.cursor_y)
-                .insert_char(self.cursor_ctrlr.cursor_x, ch);
-            self.cursor_ctrlr.cursor_x += 1;
-        } else if self.cursor_ctrlr.cursor_x >= self.cursor_ctrlr.editor_width - 1 {
-            let curr_row = self.file_ctrlr
-                .get_editor_row_mut(self.cursor_ctrlr.cursor_y);
-            curr_row.insert_char(self.cursor_ctrlr.cursor_x, ch);
-            let mut trunc_len = 0;
-            let new_row_content = if ch == ' ' || ch == '\t' {
-                String::new()
-            } else {
-                match curr_row.content.split_whitespace().last() {
-                    None => String::new(),
-                    Some(this) => {
-                        if this.len() < curr_row.len() {
-                            trunc_len = curr_row.len() - this.len();
-                            String::from(this)
-                        } else { // no whitespace in row
-                            String::new()
-                        }
-                    }
-                }
+            self.cursor_ctrlr.cursor_x = self.file_ctrlr.insert_char(
+                self.cursor_ctrlr.cursor_y, self.cursor_ctrlr.cursor_x, ch
+            );
+        } else if self.cursor_ctrlr.wrap_mode == WrapMode::Hard
+            && self.cursor_ctrlr.get_render_x(&self.file_ctrlr.get_render(self.cursor_ctrlr.cursor_y))
+                >= self.cursor_ctrlr.wrap_width().saturating_sub(1) {
+            let mut curr_row = self.file_ctrlr.get_editor_row(self.cursor_ctrlr.cursor_y);
+            let at = FileController::byte_index(&curr_row, self.cursor_ctrlr.cursor_x);
+            curr_row.insert(at, ch);
+            let after = at + ch.len_utf8();
+            let word_start = if ch.is_whitespace() {
+                None
+            } else { // the word being typed moves down, unless it's all there is before the cursor
+                curr_row[..at]
+                    .char_indices()
+                    .rfind(|(_, it)| it.is_whitespace())
+                    .map(|(idx, it)| idx + it.len_utf8())
             };
-            if trunc_len > 0 {
-                curr_row.content.truncate(trunc_len);
-                FileController::render_row(curr_row);
-            }
-            self.cursor_ctrlr.cursor_x = new_row_content.len();
-            self.cursor_ctrlr.cursor_y += 1;
-            self.file_ctrlr.insert_row(
+            let split = word_start.unwrap_or(after);
+            let new_row_content = curr_row.split_off(split);
+            self.cursor_ctrlr.cursor_x = FileController::grapheme_count(&new_row_content[..after - split]);
+            self.file_ctrlr.replace_rows(
                 self.cursor_ctrlr.cursor_y,
-                new_row_content 
+                1,
+                vec![curr_row, new_row_content]
             );
+            self.cursor_ctrlr.cursor_y += 1;
         } else {
-            self.file_ctrlr
-                .get_editor_row_mut(self.cursor_ctrlr.cursor_y)
-                .insert_char(self.cursor_ctrlr.cursor_x, ch);
-            self.cursor_ctrlr.cursor_x += 1;
+            self.cursor_ctrlr.cursor_x = self.file_ctrlr.insert_char(
+                self.cursor_ctrlr.cursor_y, self.cursor_ctrlr.cursor_x, ch
+            );
         }
-        self.dirty += 1;
     }
 
     pub fn insert_newline(&mut self) {
-        if self.cursor_ctrlr.cursor_x == 0 {
-            self.file_ctrlr.insert_row(self.cursor_ctrlr.cursor_y, String::new());
-        } else {
-            let curr_row = self.file_ctrlr
-                .get_editor_row_mut(self.cursor_ctrlr.cursor_y);
-            let new_row_content: String = curr_row.content[self.cursor_ctrlr.cursor_x..].into();
-            curr_row.content.truncate(self.cursor_ctrlr.cursor_x);
-            FileController::render_row(curr_row);
-            self.file_ctrlr.insert_row(self.cursor_ctrlr.cursor_y + 1, new_row_content)
-        }
-        self.cursor_ctrlr.cursor_x = 0;
-        self.cursor_ctrlr.cursor_y += 1;
-        self.dirty += 1
+        self.delete_selection();
+        self.record_edit(self.cursor_ctrlr.cursor_y, 1, EditKind::Other, |ctrlrs| {
+            if ctrlrs.cursor_ctrlr.cursor_x == 0 {
+                ctrlrs.file_ctrlr.insert_row(ctrlrs.cursor_ctrlr.cursor_y, String::new());
+            } else {
+                ctrlrs.file_ctrlr.split_row(ctrlrs.cursor_ctrlr.cursor_y, ctrlrs.cursor_ctrlr.cursor_x)
+            }
+            ctrlrs.cursor_ctrlr.cursor_x = 0;
+            ctrlrs.cursor_ctrlr.cursor_y += 1;
+        })
     }
 
     pub fn delete_char(&mut self, shift: KeyModifiers) {
@@ -230,39 +521,47 @@ impl Controllers {
         }
         match shift {
             KeyModifiers::SHIFT => {
-                self.delete_prev_word()
+                let row = self.cursor_ctrlr.cursor_y.saturating_sub(1);
+                self.record_edit(row, 2, EditKind::Other, Self::delete_prev_word)
             }
             _ => {
-                let row = self
-                    .file_ctrlr
-                    .get_editor_row_mut(self.cursor_ctrlr.cursor_y);
                 if self.cursor_ctrlr.cursor_x > 0 {
-                    self.cursor_ctrlr.cursor_x -= 1;
-                    row.delete_char(self.cursor_ctrlr.cursor_x);
-                    self.dirty += 1;
+                    self.record_edit(self.cursor_ctrlr.cursor_y, 1, EditKind::Delete, Self::remove_char)
                 } else {
-                    let len_prev_row = self.file_ctrlr.get_editor_row(self.cursor_ctrlr.cursor_y - 1).len();
-                    self.file_ctrlr.join_adjacent_rows(self.cursor_ctrlr.cursor_y);
-                    self.cursor_ctrlr.cursor_x = len_prev_row;
-                    self.cursor_ctrlr.cursor_y -= 1;
+                    self.record_edit(self.cursor_ctrlr.cursor_y - 1, 2, EditKind::Other, Self::remove_char)
                 }
             }
         }
     }
 
+    fn remove_char(&mut self) {
+        if self.cursor_ctrlr.cursor_y == self.file_ctrlr.count_rows()
+            || (self.cursor_ctrlr.cursor_x == 0 && self.cursor_ctrlr.cursor_y == 0) {
+            return
+        }
+        if self.cursor_ctrlr.cursor_x > 0 {
+            self.cursor_ctrlr.cursor_x -= 1;
+            self.file_ctrlr.delete_char(self.cursor_ctrlr.cursor_y, self.cursor_ctrlr.cursor_x);
+        } else {
+            let len_prev_row = self.file_ctrlr.row_len(self.cursor_ctrlr.cursor_y - 1);
+            self.file_ctrlr.join_adjacent_rows(self.cursor_ctrlr.cursor_y);
+            self.cursor_ctrlr.cursor_x = len_prev_row;
+            self.cursor_ctrlr.cursor_y -= 1;
+        }
+    }
+
     fn delete_prev_word(&mut self) { // deletes word in same row but behind the cursor_x, uses same logic as SHIFT+LEFT
         let curr_row = self.file_ctrlr.get_editor_row(self.cursor_ctrlr.cursor_y);
         let move_len = if self.cursor_ctrlr.cursor_x == 0 {
             1
         } else {
-            curr_row.content[0..self.cursor_ctrlr.cursor_x]
+            curr_row[..FileController::byte_index(&curr_row, self.cursor_ctrlr.cursor_x)]
                 .split_whitespace()
                 .last()
-                .unwrap()
-                .len() + 1 // add one to put cursor in the next whitepsace
+                .map_or(0, FileController::grapheme_count) + 1 // add one to put cursor in the next whitepsace
         };
         for _ in 0..move_len {
-            self.delete_char(KeyModifiers::NONE)
+            self.remove_char()
         };
     }
 
@@ -278,7 +577,7 @@ impl Controllers {
             }
             KeyCode::PageDown => {
                 self.cursor_ctrlr.cursor_y = cmp::min(
-                    self.cursor_ctrlr.editor_height + self.cursor_ctrlr.row_offset - 1,
+                    self.cursor_ctrlr.row_offset + self.cursor_ctrlr.editor_height.saturating_sub(1),
                     self.file_ctrlr.count_rows()
                 );
                 for _ in 0..self.cursor_ctrlr.editor_height {
@@ -296,18 +595,19 @@ impl Controllers {
                     return
                 }
                 if shift == KeyModifiers::SHIFT {
-                    let curr_row: &Row = self.file_ctrlr.get_editor_row(self.cursor_ctrlr.cursor_y);
+                    let curr_row = self.file_ctrlr.get_editor_row(self.cursor_ctrlr.cursor_y);
                     let move_len = match key {
                         KeyCode::Up | KeyCode::Down => 5,
                         KeyCode::Right => {
                             if self.cursor_ctrlr.cursor_y >= self.file_ctrlr.count_rows() 
-                                || self.cursor_ctrlr.cursor_x >= curr_row.content.len() {
+                                || self.cursor_ctrlr.cursor_x >= FileController::grapheme_count(&curr_row) {
                                 1
                             } else {
-                                match curr_row.content[self.cursor_ctrlr.cursor_x..].split_whitespace().next() {
+                                let start = FileController::byte_index(&curr_row, self.cursor_ctrlr.cursor_x);
+                                match curr_row[start..].split_whitespace().next() {
                                     None => 1,
                                     Some(word) => {
-                                        word.len() + 1 // add one to put cursor in the next whitespace
+                                        FileController::grapheme_count(word) + 1 // add one to put cursor in the next whitespace
                                     }
                                 }
                             }
@@ -317,10 +617,11 @@ impl Controllers {
                                 || self.cursor_ctrlr.cursor_x == 0 {
                                 1
                             } else {
-                                match curr_row.content[0..self.cursor_ctrlr.cursor_x].split_whitespace().last() {
+                                let end = FileController::byte_index(&curr_row, self.cursor_ctrlr.cursor_x);
+                                match curr_row[..end].split_whitespace().last() {
                                     None => 1,
                                     Some(word) => {
-                                        word.len() + 1 // add one to put cursor in the next whitespace
+                                        FileController::grapheme_count(word) + 1 // add one to put cursor in the next whitespace
                                     }
                                 }
                             }
@@ -337,36 +638,894 @@ impl Controllers {
         }
     }
 
-    pub fn save(&mut self) -> io::Result<usize> {
+    pub fn set_selecting(&mut self, selecting: bool) {
+        match (selecting, self.cursor_ctrlr.anchor) {
+            (true, None) => self.cursor_ctrlr.anchor = Some((self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y)),
+            (false, _) => self.cursor_ctrlr.anchor = None,
+            _ => {}
+        }
+    }
+
+    pub fn mouse_event(&mut self, event: MouseEvent) {
+        let point = (event.column as usize, event.row as usize);
+        if let MouseEventKind::Down(_) | MouseEventKind::ScrollUp | MouseEventKind::ScrollDown = event.kind {
+            if let Some(idx) = self.panes.pane_at(point) {
+                self.focus_pane(idx)
+            }
+        }
+        let area = self.panes.panes[self.panes.active].area;
+        let screen = (point.0.saturating_sub(area.x), point.1.saturating_sub(area.y));
+        let clamped = (screen.0, cmp::min(screen.1, self.cursor_ctrlr.editor_height.saturating_sub(1)));
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) if screen.1 < self.cursor_ctrlr.editor_height => {
+                let pos = self.cursor_ctrlr.position_at(&self.file_ctrlr, screen);
+                let double = self.last_click.is_some_and(|(time, at)| at == pos && time.elapsed() < DOUBLE_CLICK);
+                if event.modifiers.contains(KeyModifiers::SHIFT) {
+                    self.set_selecting(true)
+                } else {
+                    self.cursor_ctrlr.anchor = Some(pos)
+                }
+                (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y) = pos;
+                if double {
+                    self.select_word();
+                    self.last_click = None
+                } else {
+                    self.last_click = Some((time::Instant::now(), pos))
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                self.set_selecting(true);
+                (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y) =
+                    self.cursor_ctrlr.position_at(&self.file_ctrlr, clamped);
+            }
+            MouseEventKind::ScrollUp => self.cursor_ctrlr.scroll_view(&self.file_ctrlr, -3),
+            MouseEventKind::ScrollDown => self.cursor_ctrlr.scroll_view(&self.file_ctrlr, 3),
+            _ => {}
+        }
+    }
+
+    fn select_word(&mut self) {
+        let y = self.cursor_ctrlr.cursor_y;
+        if y >= self.file_ctrlr.count_rows() {
+            return
+        }
+        let row = self.file_ctrlr.get_editor_row(y);
+        let byte = FileController::byte_index(&row, self.cursor_ctrlr.cursor_x);
+        if let Some((start, word)) = row.split_word_bound_indices().rfind(|(start, _)| *start <= byte) {
+            let x = FileController::grapheme_count(&row[..start]);
+            self.cursor_ctrlr.anchor = Some((x, y));
+            self.cursor_ctrlr.cursor_x = x + FileController::grapheme_count(word);
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        self.cursor_ctrlr.anchor = Some((0, 0));
+        self.cursor_ctrlr.cursor_y = self.file_ctrlr.count_rows();
+        self.cursor_ctrlr.cursor_x = 0;
+    }
+
+    fn selection_range(&self) -> Option<((usize, usize), (usize, usize))> { // clamped to the end of the text
+        let (start, mut end) = self.cursor_ctrlr.selection()?;
+        let rows = self.file_ctrlr.count_rows();
+        if end.1 >= rows {
+            if rows == 0 {
+                return None
+            }
+            end = (self.file_ctrlr.row_len(rows - 1), rows - 1);
+        }
+        if start.1 > end.1 || (start.1 == end.1 && start.0 >= end.0) {
+            return None
+        }
+        Some((start, end))
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        let ((x1, y1), (x2, y2)) = self.selection_range()?;
+        let mut rows = self.file_ctrlr.row_contents(y1, y2 - y1 + 1);
+        let last = rows.len() - 1;
+        let end = FileController::byte_index(&rows[last], x2);
+        rows[last].truncate(end);
+        let start = FileController::byte_index(&rows[0], x1);
+        rows[0].drain(..start);
+        Some(rows.join("\n"))
+    }
+
+    pub fn delete_selection(&mut self) -> bool {
+        if self.selection_range().is_none() {
+            self.cursor_ctrlr.anchor = None;
+            return false
+        }
+        self.insert_text("");
+        true
+    }
+
+    pub fn copy(&mut self) {
+        if self.copy_selection("Copied") {
+            self.cursor_ctrlr.anchor = None
+        }
+    }
+
+    pub fn cut(&mut self) {
+        if self.copy_selection("Cut") {
+            self.delete_selection();
+        }
+    }
+
+    fn copy_selection(&mut self, verb: &str) -> bool {
+        let Some(text) = self.selected_text() else {
+            self.set_status_msg("Nothing selected".into());
+            return false
+        };
+        let message = match self.clipboard.copy(&text, &mut self.writing_ctrlr) {
+            Ok(()) => format!("{} {} characters", verb, text.chars().count()),
+            Err(err) => format!("{} {} characters, but only within the editor: {}", verb, text.chars().count(), err)
+        };
+        self.set_status_msg(message);
+        self.register = text;
+        true
+    }
+
+    pub fn paste(&mut self) {
+        let text = self.clipboard.paste().unwrap_or_else(|| self.register.clone());
+        if text.is_empty() {
+            self.set_status_msg("Nothing to paste".into());
+            return
+        }
+        self.insert_text(&text)
+    }
+
+    /// Replaces the selection, or inserts at the cursor, as a single undo step
+    /// without any of the transforms typing goes through.
+    fn insert_text(&mut self, text: &str) {
+        let cursor = (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y);
+        let ((x1, y1), (x2, y2)) = self.selection_range().unwrap_or((cursor, cursor));
+        self.record_edit(y1, y2 - y1 + 1, EditKind::Other, |ctrlrs| {
+            if y1 == ctrlrs.file_ctrlr.count_rows() {
+                ctrlrs.file_ctrlr.insert_row(y1, String::new());
+            }
+            let first = ctrlrs.file_ctrlr.get_editor_row(y1);
+            let last = ctrlrs.file_ctrlr.get_editor_row(y2);
+            let mut rows: Vec<String> = text.split('\n').map(String::from).collect();
+            let n = rows.len() - 1;
+            let x = FileController::grapheme_count(&rows[n]) + if n == 0 { x1 } else { 0 };
+            rows[0].insert_str(0, &first[..FileController::byte_index(&first, x1)]);
+            rows[n].push_str(&last[FileController::byte_index(&last, x2)..]);
+            ctrlrs.file_ctrlr.replace_rows(y1, y2 - y1 + 1, rows);
+            (ctrlrs.cursor_ctrlr.cursor_x, ctrlrs.cursor_ctrlr.cursor_y) = (x, y1 + n);
+        })
+    }
+
+    pub fn insert_paste(&mut self, text: &str) {
+        self.insert_text(&text.replace("\r\n", "\n").replace('\r', "\n"))
+    }
+
+    pub fn undo(&mut self) {
+        match self.history.undo() {
+            None => self.status_msg.set_message("Nothing to undo".into()),
+            Some(edit) => {
+                self.file_ctrlr.replace_rows(edit.row, edit.new_rows.len(), edit.old_rows.clone());
+                (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y) = edit.cursor_before;
+                self.cursor_ctrlr.anchor = None;
+                self.dirty = if self.history.is_saved() { 0 } else { self.dirty + 1 };
+            }
+        }
+    }
+
+    pub fn redo(&mut self) {
+        match self.history.redo() {
+            None => self.status_msg.set_message("Nothing to redo".into()),
+            Some(edit) => {
+                self.file_ctrlr.replace_rows(edit.row, edit.old_rows.len(), edit.new_rows.clone());
+                (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y) = edit.cursor_after;
+                self.cursor_ctrlr.anchor = None;
+                self.dirty = if self.history.is_saved() { 0 } else { self.dirty + 1 };
+            }
+        }
+    }
+
+    pub fn toggle_wrap_mode(&mut self) {
+        let (mode, name) = match self.cursor_ctrlr.wrap_mode {
+            WrapMode::Soft => (WrapMode::Hard, "hard"),
+            WrapMode::Hard => (WrapMode::Soft, "soft")
+        };
+        self.cursor_ctrlr.wrap_mode = mode;
+        self.set_status_msg(format!("Wrap mode: {}", name));
+    }
+
+    pub fn resize(&mut self, (width, height): (usize, usize)) {
+        self.screen = (width, height);
+        self.cursor_ctrlr.resize((width, height.saturating_sub(2))); // until the panes are laid out again
+        self.cursor_ctrlr.scroll(&self.file_ctrlr)
+    }
+
+    pub fn set_text_width(&mut self, width: &str) {
+        match Self::parse_columns(width, "full") {
+            Ok(width) => {
+                self.cursor_ctrlr.text_width = width;
+                self.cursor_ctrlr.update_layout();
+                self.set_status_msg(format!("Text width: {} columns", self.cursor_ctrlr.editor_width));
+            }
+            Err(msg) => self.set_status_msg(msg)
+        }
+    }
+
+    pub fn set_wrap_column(&mut self, column: &str) {
+        match Self::parse_columns(column, "width") {
+            Ok(column) => {
+                self.cursor_ctrlr.wrap_column = column;
+                self.set_status_msg(format!("Wrapping at column {}", self.cursor_ctrlr.wrap_width()));
+            }
+            Err(msg) => self.set_status_msg(msg)
+        }
+    }
+
+    fn parse_columns(value: &str, unset: &str) -> Result<Option<usize>, String> {
+        let value = value.trim();
+        if value == unset {
+            return Ok(None)
+        }
+        match value.parse::<usize>() {
+            Ok(columns) if columns >= 10 => Ok(Some(columns)),
+            _ => Err(format!("Expected a number of columns (at least 10) or '{}', got '{}'", unset, value))
+        }
+    }
+
+    pub fn toggle_centered(&mut self) {
+        self.cursor_ctrlr.centered = !self.cursor_ctrlr.centered;
+        self.cursor_ctrlr.update_layout();
+        self.set_status_msg(format!(
+            "Zen layout {}",
+            if self.cursor_ctrlr.centered { "on" } else { "off" }
+        ));
+    }
+
+    pub fn start_search(&mut self) {
+        self.search.start(self.cursor_ctrlr.view())
+    }
+
+    pub fn find_callback(&mut self, query: &str, key: KeyCode) {
+        let from = match key {
+            KeyCode::Esc => {
+                if let Some(view) = self.search.finish() {
+                    self.cursor_ctrlr.set_view(view)
+                }
+                return
+            }
+            KeyCode::Enter if !query.is_empty() => {
+                self.search.finish();
+                return
+            }
+            KeyCode::Right | KeyCode::Down => self.search.last_match.map(|(x, y)| ((x + 1, y), true)),
+            KeyCode::Left | KeyCode::Up => self.search.last_match.map(|(x, y)| ((x, y), false)),
+            _ => {
+                self.search.query = Some(query.to_string());
+                None
+            }
+        };
+        let (from, forward) = from.unwrap_or_else(|| {
+            (self.search.saved_view.map_or((0, 0), |view| view.cursor), true)
+        });
+        match self.search.search(&self.file_ctrlr, from, forward) {
+            Some((x, y)) => {
+                self.cursor_ctrlr.cursor_x = x;
+                self.cursor_ctrlr.cursor_y = y;
+            }
+            None => {
+                if let Some(view) = self.search.saved_view {
+                    (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y) = view.cursor;
+                }
+            }
+        }
+    }
+
+    pub fn open_picker(&mut self, items: Vec<(String, String)>) {
+        self.picker = Some(Picker::new(items))
+    }
+
+    pub fn picker_callback(&mut self, query: &str, key: KeyCode) {
+        if let Some(picker) = self.picker.as_mut() {
+            picker.update(query, key);
+            if picker.preview.is_some() {
+                picker.preview = Some(picker.selected_label().map_or_else(Vec::new, |path| {
+                    FileController::preview(Path::new(path), self.cursor_ctrlr.editor_height)
+                }))
+            }
+        }
+    }
+
+    pub fn close_picker(&mut self) -> Option<usize> {
+        self.picker.take().and_then(|picker| picker.selected())
+    }
+
+    pub fn open_file_picker(&mut self) {
+        let files = FileController::project_files(MAX_PICKER_FILES);
+        let bonus = files.iter().map(|path| if path.ends_with(".md") { MARKDOWN_BONUS } else { 0 }).collect();
+        let mut picker = Picker::new(files.into_iter().map(|path| (path, String::new())).collect()).with_bonus(bonus);
+        picker.preview = Some(Vec::new());
+        self.picker = Some(picker);
+        self.picker_callback("", KeyCode::Null)
+    }
+
+    /// The path to open for what was typed: an existing path or a name that no file
+    /// closely matches is taken as typed, otherwise the selected file.
+    pub fn close_file_picker(&mut self, typed: &str) -> Option<String> {
+        let picker = self.picker.take();
+        let selected = picker.as_ref().and_then(|picker| picker.selected_label().map(String::from));
+        match selected {
+            _ if typed.is_empty() => selected,
+            _ if Path::new(typed).exists() => Some(typed.into()),
+            Some(path) if picker.is_some_and(|picker| picker.moved()) || Self::names_file(typed, &path) => Some(path),
+            _ => Some(typed.into())
+        }
+    }
+
+    fn names_file(typed: &str, path: &str) -> bool { // "main" or "src/main.rs" for src/main.rs, not "main.c"
+        let has_extension = Path::new(typed).extension().is_some();
+        path.match_indices(typed)
+            .any(|(idx, _)| (idx == 0 || path[..idx].ends_with('/')) && (!has_extension || idx + typed.len() == path.len()))
+    }
+
+    pub fn start_replace(&mut self, regex: Regex, template: String) {
+        let bounds = self.selection_range().map(|((x1, y1), (x2, y2))| {
+            let to_byte = |x, y| FileController::byte_index(&self.file_ctrlr.get_editor_row(y), x);
+            ((to_byte(x1, y1), y1), (to_byte(x2, y2), y2))
+        });
+        self.cursor_ctrlr.anchor = None;
+        self.replace = Some(ReplaceController::new(
+            regex,
+            template,
+            (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y),
+            bounds
+        ))
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.selection_range().is_some()
+    }
+
+    pub fn next_replace_match(&mut self) -> bool {
+        let Some(replace) = self.replace.as_mut() else { return false };
+        match replace.next_match(&self.file_ctrlr) {
+            Some((row_idx, start, _)) => {
+                let row = self.file_ctrlr.get_editor_row(row_idx);
+                self.cursor_ctrlr.cursor_x = FileController::grapheme_count(&row[..start]);
+                self.cursor_ctrlr.cursor_y = row_idx;
+                true
+            }
+            None => false
+        }
+    }
+
+    pub fn replace_match(&mut self) {
+        let Some(replace) = self.replace.as_mut() else { return };
+        if let Some((row_idx, new_row)) = replace.replace_current(&self.file_ctrlr) {
+            self.file_ctrlr.replace_rows(row_idx, 1, vec![new_row]);
+        }
+    }
+
+    pub fn finish_replace(&mut self) -> usize { // records every replacement as a single undo step
+        let Some(replace) = self.replace.take() else { return 0 };
+        let (Some(first), Some(last)) = (
+            replace.originals.keys().next().copied(),
+            replace.originals.keys().last().copied()
+        ) else {
+            (self.cursor_ctrlr.cursor_x, self.cursor_ctrlr.cursor_y) = replace.cursor_before;
+            return 0
+        };
+        let old_rows = (first..=last)
+            .map(|idx| replace.originals.get(&idx).cloned().unwrap_or_else(|| self.file_ctrlr.get_editor_row(idx)))
+            .collect();
+        self.push_edit(first, old_rows, last - first + 1, replace.cursor_before, EditKind::Other);
+        replace.replaced
+    }
+
+    pub fn save(&mut self) -> error::Result<usize> {
+        let len = self.file_ctrlr
+            .save()
+            .map_err(|err| EditorError::file("save", self.file_ctrlr.filename.as_deref(), err))?;
         self.dirty = 0;
-        self.file_ctrlr.save()
+        self.history.mark_saved();
+        let _ = self.file_ctrlr.update_swap(0); // a leftover swap would only cause a needless recovery prompt
+        Ok(len)
+    }
+
+    fn convert(&mut self, change: impl FnOnce(&mut FileFormat) -> Result<(), String>) {
+        let mut format = self.file_ctrlr.format;
+        match change(&mut format) {
+            Ok(()) if format != self.file_ctrlr.format => {
+                self.file_ctrlr.format = format;
+                self.dirty += 1;
+                self.history.mark_unsaved(); // undoing edits doesn't undo the conversion
+                self.set_status_msg(format!("Will be saved as {}", format))
+            }
+            Ok(()) => self.set_status_msg(format!("Already {}", format)),
+            Err(err) => self.set_status_msg(err)
+        }
+    }
+
+    pub fn set_encoding(&mut self, name: &str) {
+        self.convert(|format| format.parse_encoding(name))
+    }
+
+    pub fn set_line_ending(&mut self, name: &str) {
+        self.convert(|format| format.parse_line_ending(name))
+    }
+
+    pub fn toggle_final_newline(&mut self) {
+        self.convert(|format| {
+            format.final_newline = !format.final_newline;
+            Ok(())
+        })
     }
 
     pub fn set_status_msg(&mut self, s: String) {
         self.status_msg.set_message(s);
     }
 
+    pub fn report_startup_error(&mut self, error: String) { // next to the ones found while loading
+        let message = match self.status_msg.message() {
+            Some(message) if *message != self.config.help_text => format!("{} / {}", message, error),
+            _ => error
+        };
+        self.set_status_msg(message)
+    }
+
     pub fn attempt_to_quit(&mut self) -> bool {
-        if self.dirty > 0 && self.quit_attempts < MAX_QUIT_ATTEMPTS {
+        let dirty: Vec<String> = self.documents()
+            .into_iter()
+            .filter(|(_, dirty)| *dirty > 0)
+            .map(|(file_ctrlr, _)| Document::name(file_ctrlr))
+            .collect();
+        if !dirty.is_empty() && self.quit_attempts < self.config.max_quit_attempts {
             self.set_status_msg(
                 format!(
-                    "WARNING! File has unsaved changes. \
+                    "WARNING! Unsaved changes in {}. \
                     Press Ctrl+C {} more times to quit without saving or Ctrl+S to save first.",
-                    MAX_QUIT_ATTEMPTS - self.quit_attempts
+                    dirty.join(", "),
+                    self.config.max_quit_attempts - self.quit_attempts
                 )
             );
             self.quit_attempts += 1;
             false // not quitting
         } else {
+            self.clean_exit = true;
             true // quitting
         }
     }
 
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    pub fn key_overrides(&self) -> &[(String, Vec<String>)] {
+        &self.config.keys
+    }
+
     pub fn loaded_from_file(&self) -> bool {
         self.file_ctrlr.filename.is_some()
     }
 
     pub fn set_filename(&mut self, filename: Option<PathBuf>) {
+        if matches!(self.file_ctrlr.swap, SwapState::Written(_) | SwapState::Failed(_)) {
+            let _ = self.file_ctrlr.remove_swap();
+        }
+        self.file_ctrlr.swap = match SwapState::detect(filename.as_deref()) {
+            SwapState::Found => SwapState::Deferred, // someone else's, don't overwrite it
+            state => state
+        };
         self.file_ctrlr.filename = filename
     }
-}
\ No newline at end of file
+
+    fn documents(&self) -> Vec<(&FileController, u64)> { // (file, dirty) of every buffer, in order
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(idx, document)| {
+                if idx == self.current {
+                    (&self.file_ctrlr, self.dirty)
+                } else {
+                    (&document.file_ctrlr, document.dirty)
+                }
+            })
+            .collect()
+    }
+
+    fn documents_mut(&mut self) -> Vec<(&mut FileController, u64)> {
+        let current = self.current;
+        let mut active = Some((&mut self.file_ctrlr, self.dirty));
+        self.buffers
+            .iter_mut()
+            .enumerate()
+            .map(|(idx, document)| match active.take_if(|_| idx == current) {
+                Some(active) => active,
+                None => (&mut document.file_ctrlr, document.dirty)
+            })
+            .collect()
+    }
+
+    fn swap_document(&mut self, idx: usize) { // exchanges the active document with the one stored at `idx`
+        let document = &mut self.buffers[idx];
+        mem::swap(&mut self.file_ctrlr, &mut document.file_ctrlr);
+        mem::swap(&mut self.history, &mut document.history);
+        mem::swap(&mut self.syntax, &mut document.syntax);
+        mem::swap(&mut self.dirty, &mut document.dirty);
+        let view = self.cursor_ctrlr.view();
+        self.cursor_ctrlr.set_view(document.view);
+        document.view = view;
+    }
+
+    pub fn switch_buffer(&mut self, idx: usize) {
+        if idx != self.current {
+            self.swap_document(self.current); // put the active document away
+            self.swap_document(idx);
+            self.cursor_ctrlr.clamp(&self.file_ctrlr);
+            self.current = idx;
+            self.panes.panes[self.panes.active].buffer = idx;
+            self.last_click = None;
+        }
+        self.set_status_msg(format!(
+            "Buffer {}/{}: {}",
+            idx + 1,
+            self.buffers.len(),
+            Document::name(&self.file_ctrlr)
+        ));
+        self.check_disk();
+    }
+
+    pub fn cycle_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len();
+        self.switch_buffer(if forward { (self.current + 1) % count } else { (self.current + count - 1) % count })
+    }
+
+    pub fn open_file(&mut self, path: PathBuf) {
+        let open = self.documents().iter().position(|(file_ctrlr, _)| file_ctrlr.filename.as_ref() == Some(&path));
+        if let Some(idx) = open {
+            return self.switch_buffer(idx)
+        }
+        let file_ctrlr = match FileController::open(path.clone(), self.config.tab_stop) {
+            Ok(file_ctrlr) => file_ctrlr,
+            Err(err) => return self.set_status_msg(EditorError::file("open", Some(&path), err).to_string())
+        };
+        if self.file_ctrlr.filename.is_none() && self.dirty == 0 && self.file_ctrlr.count_rows() == 0 {
+            // replace the untouched scratch buffer rather than keeping it around
+            self.swap_document(self.current);
+            self.buffers[self.current] = Document::new(file_ctrlr);
+            self.swap_document(self.current);
+            self.switch_buffer(self.current)
+        } else {
+            self.buffers.push(Document::new(file_ctrlr));
+            self.switch_buffer(self.buffers.len() - 1)
+        }
+    }
+
+    pub fn split_pane(&mut self, vertical: bool) {
+        let view = self.cursor_ctrlr.view();
+        self.panes.panes[self.panes.active].view = view;
+        self.panes.split(vertical, view);
+    }
+
+    pub fn close_pane(&mut self) {
+        if self.panes.close() {
+            self.load_pane()
+        } else {
+            self.set_status_msg("This is the only pane".into())
+        }
+    }
+
+    pub fn focus_pane(&mut self, idx: usize) {
+        if idx != self.panes.active {
+            self.panes.panes[self.panes.active].view = self.cursor_ctrlr.view();
+            self.panes.active = idx;
+            self.load_pane()
+        }
+    }
+
+    pub fn cycle_pane(&mut self, forward: bool) {
+        self.focus_pane(self.panes.next(forward))
+    }
+
+    pub fn resize_pane(&mut self, delta: isize) {
+        self.panes.resize(delta)
+    }
+
+    fn load_pane(&mut self) { // makes the active pane's buffer and view the ones being edited
+        let (buffer, view, area) = {
+            let pane = &self.panes.panes[self.panes.active];
+            (pane.buffer, pane.view, pane.area)
+        };
+        if buffer != self.current {
+            self.swap_document(self.current);
+            self.swap_document(buffer);
+            self.current = buffer;
+        }
+        self.cursor_ctrlr.set_view(view);
+        self.cursor_ctrlr.clamp(&self.file_ctrlr);
+        self.cursor_ctrlr.resize((area.width, area.height.saturating_sub(1)));
+        self.last_click = None;
+        self.check_disk();
+    }
+
+    pub fn idle_timeout(&self) -> time::Duration {
+        self.config.autosave_idle
+    }
+
+    /// Writes the swap files of documents changed since the last call and
+    /// checks the active file on disk, returning whether the screen needs redrawing.
+    pub fn idle(&mut self) -> bool {
+        let errors: Vec<String> = self.documents_mut()
+            .into_iter()
+            .filter_map(|(file_ctrlr, dirty)| {
+                let err = file_ctrlr.update_swap(dirty).err()?;
+                Some(EditorError::file("write swap file", Some(&file_ctrlr.swap_path()), err).to_string())
+            })
+            .collect();
+        if !errors.is_empty() {
+            self.set_status_msg(errors.join(" / "));
+        }
+        self.check_disk() || !errors.is_empty()
+    }
+
+    pub fn next_recovery(&self) -> Option<usize> { // a buffer whose swap file hasn't been dealt with yet
+        if self.readonly {
+            return None // recovering is an edit, the swap stays for a later session
+        }
+        self.documents().iter().position(|(file_ctrlr, _)| file_ctrlr.swap == SwapState::Found)
+    }
+
+    fn read_swap(&self) -> error::Result<Vec<String>> {
+        let path = self.file_ctrlr.swap_path();
+        FileController::read_rows(&path).map_err(|err| EditorError::file("read swap file", Some(&path), err))
+    }
+
+    pub fn swap_summary(&self) -> error::Result<String> {
+        let current = self.file_ctrlr.row_contents(0, self.file_ctrlr.count_rows());
+        Ok(SwapState::summary(&self.file_ctrlr.swap_path(), &current, &self.read_swap()?))
+    }
+
+    fn replace_text(&mut self, rows: Vec<String>) { // as one undoable edit
+        let count = self.file_ctrlr.count_rows();
+        self.record_edit(0, count, EditKind::Other, |ctrlrs| ctrlrs.file_ctrlr.replace_rows(0, count, rows));
+        self.cursor_ctrlr.clamp(&self.file_ctrlr);
+    }
+
+    pub fn recover_swap(&mut self) -> error::Result<()> { // the file is only changed on save
+        let rows = self.read_swap()?;
+        self.replace_text(rows);
+        self.file_ctrlr.swap = SwapState::Written(self.dirty);
+        Ok(())
+    }
+
+    pub fn discard_swap(&mut self) -> error::Result<()> {
+        let path = self.file_ctrlr.swap_path();
+        self.file_ctrlr.remove_swap().map_err(|err| EditorError::file("remove swap file", Some(&path), err))
+    }
+
+    pub fn defer_swap(&mut self) {
+        self.file_ctrlr.swap = SwapState::Deferred
+    }
+
+    pub fn compare_swap(&mut self) -> error::Result<()> { // shows the swap in a split next to the document
+        let path = self.file_ctrlr.swap_path();
+        if self.documents().iter().any(|(file_ctrlr, _)| file_ctrlr.filename.as_ref() == Some(&path)) {
+            return Ok(())
+        }
+        let file_ctrlr = FileController::open(path.clone(), self.config.tab_stop)
+            .map_err(|err| EditorError::file("open", Some(&path), err))?;
+        let pane = self.panes.active;
+        self.buffers.push(Document::new(file_ctrlr));
+        self.split_pane(true);
+        self.switch_buffer(self.buffers.len() - 1);
+        self.focus_pane(pane);
+        Ok(())
+    }
+
+    /// Notices when the active file changed on disk and reloads it if there's
+    /// nothing to lose. Returns whether the screen needs redrawing.
+    pub fn check_disk(&mut self) -> bool {
+        match self.file_ctrlr.check_disk() {
+            Ok(true) if self.dirty == 0 => {
+                self.reload_from_disk();
+                self.set_status_msg(format!("Reloaded {}, it changed on disk", Document::name(&self.file_ctrlr)));
+                true
+            }
+            Ok(changed) => {
+                self.disk_prompt |= changed;
+                false
+            }
+            Err(_) => false // unreadable for now, saving reports real problems
+        }
+    }
+
+    pub fn take_disk_prompt(&mut self) -> bool {
+        mem::take(&mut self.disk_prompt) && self.file_ctrlr.has_external()
+    }
+
+    pub fn has_external_change(&self) -> bool {
+        self.file_ctrlr.has_external()
+    }
+
+    pub fn reload_from_disk(&mut self) {
+        if let Some(rows) = self.file_ctrlr.take_external() {
+            self.replace_text(rows);
+            self.dirty = 0;
+            self.history.mark_saved();
+            let _ = self.file_ctrlr.update_swap(0);
+        }
+    }
+
+    pub fn keep_yours(&mut self) { // saving then overwrites the version on disk
+        self.file_ctrlr.take_external();
+    }
+
+    pub fn merge_from_disk(&mut self) -> usize { // returns the number of conflicts
+        let base = self.file_ctrlr.base();
+        let Some(theirs) = self.file_ctrlr.take_external() else { return 0 };
+        let (rows, conflicts) = merge::merge(&base, &self.file_ctrlr.row_contents(0, self.file_ctrlr.count_rows()), &theirs);
+        self.replace_text(rows);
+        conflicts
+    }
+
+    pub fn buffer_name(&self) -> String {
+        Document::name(&self.file_ctrlr)
+    }
+
+    pub fn buffer_list(&self) -> Vec<(String, String)> { // (name, state) for the picker
+        self.documents()
+            .into_iter()
+            .map(|(file_ctrlr, dirty)| {
+                (Document::name(file_ctrlr), if dirty > 0 { "modified".into() } else { String::new() })
+            })
+            .collect()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_text(text: &str) -> Controllers {
+        let mut ctrlrs = Controllers::default();
+        ctrlrs.file_ctrlr = FileController::from_bytes(text.as_bytes(), 4);
+        ctrlrs
+    }
+
+    #[test]
+    fn replace_highlight_after_multibyte_text() {
+        let mut ctrlrs = with_text("éé foo\n");
+        let mut replace = ReplaceController::new(Regex::new("foo").unwrap(), String::new(), (0, 0), None);
+        assert_eq!(replace.next_match(&ctrlrs.file_ctrlr), Some((0, 5, 8)));
+        ctrlrs.replace = Some(replace);
+        let highlights = ctrlrs.row_highlights(0, 6);
+        assert_eq!(highlights[..3], [Highlight::Normal; 3]);
+        assert_eq!(highlights[3..], [Highlight::SearchMatch; 3]);
+    }
+
+    #[test]
+    fn word_jumps_over_clusters() {
+        let mut ctrlrs = with_text("héllo cafe\u{301} 日本 \u{1f468}\u{200d}\u{1f467} end\n");
+        ctrlrs.cursor_ctrlr.resize((80, 24));
+        let mut stops = Vec::new();
+        for _ in 0..4 {
+            ctrlrs.move_cursor(KeyCode::Right, KeyModifiers::SHIFT);
+            stops.push(ctrlrs.cursor_ctrlr.cursor_x);
+        }
+        for _ in 0..4 {
+            ctrlrs.move_cursor(KeyCode::Left, KeyModifiers::SHIFT);
+            stops.push(ctrlrs.cursor_ctrlr.cursor_x);
+        }
+        assert_eq!(stops, [6, 11, 14, 16, 14, 11, 6, 0]);
+    }
+
+    #[test]
+    fn resize_rescrolls_to_the_cursor() {
+        let options = Options { config: Some("/nonexistent/config.toml".into()), ..Options::default() };
+        let mut ctrlrs = Controllers::with_screen(options, || Ok((40, 10)));
+        assert_eq!((ctrlrs.cursor_ctrlr.editor_width, ctrlrs.cursor_ctrlr.editor_height), (40, 8));
+        ctrlrs.file_ctrlr = FileController::from_bytes(format!("{}\n", "x".repeat(60)).repeat(30).as_bytes(), 4);
+        ctrlrs.cursor_ctrlr.wrap_mode = WrapMode::Hard;
+        (ctrlrs.cursor_ctrlr.cursor_x, ctrlrs.cursor_ctrlr.cursor_y) = (50, 20);
+        ctrlrs.cursor_ctrlr.scroll(&ctrlrs.file_ctrlr);
+        assert_eq!((ctrlrs.cursor_ctrlr.column_offset, ctrlrs.cursor_ctrlr.row_offset), (11, 13));
+
+        ctrlrs.resize((20, 6));
+        assert_eq!((ctrlrs.cursor_ctrlr.editor_width, ctrlrs.cursor_ctrlr.editor_height), (20, 4));
+        assert_eq!((ctrlrs.cursor_ctrlr.column_offset, ctrlrs.cursor_ctrlr.row_offset), (31, 17));
+        ctrlrs.resize((100, 40));
+        assert_eq!((ctrlrs.cursor_ctrlr.editor_width, ctrlrs.cursor_ctrlr.editor_height), (100, 38));
+        assert_eq!((ctrlrs.cursor_ctrlr.column_offset, ctrlrs.cursor_ctrlr.row_offset), (31, 17)); // still in view
+    }
+
+    #[test]
+    fn typed_path_wins_over_loose_matches() {
+        let mut ctrlrs = Controllers::default();
+        let mut open = |typed: &str, items: &[&str], moves: usize| {
+            ctrlrs.picker = Some(Picker::new(items.iter().map(|path| (path.to_string(), String::new())).collect()));
+            ctrlrs.picker_callback(typed, KeyCode::Null);
+            (0..moves).for_each(|_| ctrlrs.picker_callback(typed, KeyCode::Down));
+            ctrlrs.close_file_picker(typed)
+        };
+        assert_eq!(open("main", &["src/main.rs"], 0).as_deref(), Some("src/main.rs"));
+        assert_eq!(open("main.rs", &["src/main.rs"], 0).as_deref(), Some("src/main.rs"));
+        assert_eq!(open("new.txt", &["src/new.txt.bak"], 0).as_deref(), Some("new.txt"));
+        assert_eq!(open("nw", &["src/new.rs"], 0).as_deref(), Some("nw"));
+        assert_eq!(open("ain", &["src/main.rs"], 0).as_deref(), Some("ain"));
+        assert_eq!(open("s", &["src/a.rs", "src/b.rs"], 1).as_deref(), Some("src/b.rs")); // picked with the arrows
+        assert_eq!(open("Cargo.toml", &["src/Cargo.toml", "Cargo.toml"], 0).as_deref(), Some("Cargo.toml")); // exists
+        assert_eq!(open("", &["README.md"], 0).as_deref(), Some("README.md"));
+        assert_eq!(open("", &[], 0), None);
+    }
+
+    #[test]
+    fn hard_wrap_moves_the_typed_word() {
+        let typed = |row: &str, cursor_x: usize, ch: char| {
+            let mut ctrlrs = with_text(&format!("{}\n", row));
+            ctrlrs.cursor_ctrlr.wrap_mode = WrapMode::Hard;
+            ctrlrs.cursor_ctrlr.resize((10, 5));
+            ctrlrs.cursor_ctrlr.cursor_x = cursor_x;
+            ctrlrs.write_char(ch);
+            let rows = ctrlrs.file_ctrlr.row_contents(0, ctrlrs.file_ctrlr.count_rows());
+            (rows, (ctrlrs.cursor_ctrlr.cursor_x, ctrlrs.cursor_ctrlr.cursor_y))
+        };
+        assert_eq!(typed("héllo wörld ünd", 9, 'X'), (vec!["héllo ".into(), "wörXld ünd".into()], (4, 1)));
+        assert_eq!(typed("aaaaaaaaaaaa é ", 10, 'b'), (vec!["aaaaaaaaaab".into(), "aa é ".into()], (0, 1)));
+        assert_eq!(typed("héllo wörld", 11, 'é'), (vec!["héllo ".into(), "wörldé".into()], (6, 1)));
+        assert_eq!(typed("héllo wörld", 9, ' '), (vec!["héllo wör ".into(), "ld".into()], (0, 1)));
+    }
+
+    #[test]
+    fn replace_highlight_inside_a_cluster() {
+        let mut ctrlrs = with_text("cafe\u{301}\n");
+        let mut replace = ReplaceController::new(Regex::new(r"\p{Mn}").unwrap(), String::new(), (0, 0), None);
+        assert_eq!(replace.next_match(&ctrlrs.file_ctrlr), Some((0, 4, 6)));
+        ctrlrs.replace = Some(replace);
+        let highlights = ctrlrs.row_highlights(0, 4);
+        assert_eq!(highlights, [Highlight::Normal, Highlight::Normal, Highlight::Normal, Highlight::SearchMatch]);
+        ctrlrs.cursor_ctrlr.anchor = Some((0, 0));
+        ctrlrs.cursor_ctrlr.cursor_x = 9; // past the end, e.g. before clamping
+        assert_eq!(ctrlrs.row_highlights(0, 4), [Highlight::Selection; 4]);
+    }
+
+    #[test]
+    fn undo_redo_round_trip_and_dirty_state() {
+        let mut ctrlrs = with_text("héllo\n");
+        ctrlrs.cursor_ctrlr.cursor_x = 5;
+        " wörld".chars().for_each(|ch| ctrlrs.insert_char(ch));
+        ctrlrs.history.mark_saved();
+        ctrlrs.dirty = 0;
+        ctrlrs.insert_newline();
+        "日本".chars().for_each(|ch| ctrlrs.insert_char(ch));
+        let text = |ctrlrs: &Controllers| ctrlrs.file_ctrlr.row_contents(0, ctrlrs.file_ctrlr.count_rows()).join("\n");
+        assert_eq!(text(&ctrlrs), "héllo wörld\n日本");
+
+        ctrlrs.undo(); // the typing on the new row is one step
+        assert_eq!(text(&ctrlrs), "héllo wörld\n");
+        assert!(ctrlrs.dirty > 0);
+        ctrlrs.undo();
+        assert_eq!((text(&ctrlrs), ctrlrs.dirty), ("héllo wörld".into(), 0)); // back at the save point
+        assert_eq!((ctrlrs.cursor_ctrlr.cursor_x, ctrlrs.cursor_ctrlr.cursor_y), (11, 0));
+        ctrlrs.undo();
+        assert_eq!(text(&ctrlrs), "héllo");
+        assert!(ctrlrs.dirty > 0);
+
+        ctrlrs.redo();
+        assert_eq!((text(&ctrlrs), ctrlrs.dirty), ("héllo wörld".into(), 0));
+        ctrlrs.redo();
+        ctrlrs.redo();
+        assert_eq!(text(&ctrlrs), "héllo wörld\n日本");
+        assert_eq!((ctrlrs.cursor_ctrlr.cursor_x, ctrlrs.cursor_ctrlr.cursor_y), (2, 1));
+        assert!(ctrlrs.dirty > 0);
+    }
+
+    #[test]
+    fn paging_without_room_for_text() { // a terminal with only the bars left
+        let mut ctrlrs = with_text("one\ntwo\nthree\n");
+        for wrap_mode in [WrapMode::Hard, WrapMode::Soft] {
+            ctrlrs.cursor_ctrlr.wrap_mode = wrap_mode;
+            ctrlrs.cursor_ctrlr.resize((0, 0));
+            ctrlrs.move_cursor(KeyCode::PageDown, KeyModifiers::NONE);
+            ctrlrs.cursor_ctrlr.scroll(&ctrlrs.file_ctrlr);
+            ctrlrs.move_cursor(KeyCode::PageUp, KeyModifiers::NONE);
+            ctrlrs.cursor_ctrlr.scroll(&ctrlrs.file_ctrlr);
+            assert_eq!(ctrlrs.cursor_ctrlr.cursor_y, 0);
+        }
+    }
+}
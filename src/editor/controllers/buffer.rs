@@ -0,0 +1,195 @@
+use crate::editor::controllers::encoding::FileFormat;
+use ropey::{Rope, RopeBuilder};
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Rope backed document storage. Every row is stored with a trailing `\n`,
+/// so an empty buffer has no rows and the rope always ends on a line break.
+/// Clones are cheap, they share the rope's chunks until either side changes.
+#[derive(Clone)]
+pub struct TextBuffer {
+    rope: Rope
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self {
+            rope: Rope::new()
+        }
+    }
+
+    pub fn from_lines<'a>(lines: impl Iterator<Item = &'a str>) -> Self {
+        let mut builder = RopeBuilder::new();
+        lines.for_each(|line| {
+            builder.append(line);
+            builder.append("\n");
+        });
+        Self {
+            rope: builder.finish()
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines() - 1
+    }
+
+    pub fn line(&self, idx: usize) -> String {
+        let line = self.rope.line(idx);
+        line.slice(..line.len_chars() - 1).to_string()
+    }
+
+    pub fn lines(&self, range: Range<usize>) -> Vec<String> {
+        range.map(|idx| self.line(idx)).collect()
+    }
+
+    /// Char offset into the whole text of byte `byte_col` of `line`.
+    pub fn pos_to_char(&self, line: usize, byte_col: usize) -> usize {
+        self.rope.byte_to_char(self.rope.line_to_byte(line) + byte_col)
+    }
+
+    pub fn insert(&mut self, line: usize, byte_col: usize, text: &str) {
+        let idx = self.pos_to_char(line, byte_col);
+        self.rope.insert(idx, text)
+    }
+
+    pub fn remove(&mut self, line: usize, byte_cols: Range<usize>) {
+        let start = self.pos_to_char(line, byte_cols.start);
+        let end = self.pos_to_char(line, byte_cols.end);
+        self.rope.remove(start..end)
+    }
+
+    pub fn insert_line(&mut self, idx: usize, content: &str) {
+        let at = self.rope.line_to_char(idx);
+        self.rope.insert_char(at, '\n');
+        self.rope.insert(at, content)
+    }
+
+    pub fn replace_lines(&mut self, idx: usize, count: usize, contents: &[String]) {
+        let start = self.rope.line_to_char(idx);
+        let end = self.rope.line_to_char(idx + count);
+        self.rope.remove(start..end);
+        let text: String = contents.iter().fold(String::new(), |mut acc, line| {
+            acc.push_str(line);
+            acc.push('\n');
+            acc
+        });
+        self.rope.insert(start, &text)
+    }
+
+    pub fn join_lines(&mut self, idx: usize) { // joins row idx onto the row above it
+        let line_break = self.rope.line_to_char(idx) - 1;
+        self.rope.remove(line_break..line_break + 1)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W, format: &FileFormat) -> io::Result<usize> { // returns the bytes written
+        if self.rope.len_chars() == 0 {
+            return Ok(0)
+        }
+        let end = if format.final_newline { self.rope.len_chars() } else { self.rope.len_chars() - 1 };
+        let mut written = format.bom_bytes().len();
+        writer.write_all(format.bom_bytes())?;
+        for chunk in self.rope.slice(..end).chunks() {
+            let bytes = format.encode(chunk)?;
+            writer.write_all(&bytes)?;
+            written += bytes.len();
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct VecBuffer { // rows in a Vec, the way documents were stored before the rope
+        rows: Vec<String>
+    }
+
+    impl VecBuffer {
+        fn line_count(&self) -> usize {
+            self.rows.len()
+        }
+
+        fn line(&self, idx: usize) -> String {
+            self.rows[idx].clone()
+        }
+
+        fn insert(&mut self, line: usize, byte_col: usize, text: &str) {
+            match text {
+                "\n" => {
+                    let rest = self.rows[line].split_off(byte_col);
+                    self.rows.insert(line + 1, rest)
+                }
+                _ => self.rows[line].insert_str(byte_col, text)
+            }
+        }
+
+        fn remove(&mut self, line: usize, byte_cols: Range<usize>) {
+            self.rows[line].replace_range(byte_cols, "")
+        }
+
+        fn join_lines(&mut self, idx: usize) {
+            let row = self.rows.remove(idx);
+            self.rows[idx - 1].push_str(&row)
+        }
+
+        fn write_to<W: Write>(&self, mut writer: W, _format: &FileFormat) -> io::Result<()> {
+            self.rows.iter().try_for_each(|row| writeln!(writer, "{}", row))
+        }
+    }
+
+    fn sample(lines: usize) -> Vec<String> {
+        (0..lines).map(|idx| format!("{:>6} fn main() {{ println!(\"héllo, 世界\"); }}", idx)).collect()
+    }
+
+    macro_rules! workload { // typing, Enter and Backspace spread over the file, then a save
+        ($buffer:expr, $edits:expr) => {{
+            let start = Instant::now();
+            for i in 0..$edits {
+                let row = i * 7919 % ($buffer.line_count() - 1);
+                $buffer.insert(row, 0, "é");
+                $buffer.insert(row, "é".len(), "\n");
+                $buffer.join_lines(row + 1);
+                $buffer.remove(row, 0.."é".len());
+                assert!($buffer.line(row).ends_with("); }"));
+            }
+            $buffer.write_to(io::sink(), &FileFormat::default()).unwrap();
+            start.elapsed()
+        }};
+    }
+
+    fn run_both(lines: usize, edits: usize) -> (TextBuffer, VecBuffer, Duration, Duration) {
+        let rows = sample(lines);
+        let mut rope = TextBuffer::from_lines(rows.iter().map(String::as_str));
+        let mut vec = VecBuffer { rows };
+        let rope_time = workload!(rope, edits);
+        let vec_time = workload!(vec, edits);
+        (rope, vec, rope_time, vec_time)
+    }
+
+    #[test]
+    fn same_text_as_a_vec_of_rows() {
+        let (rope, vec, ..) = run_both(500, 2000);
+        assert_eq!(rope.lines(0..rope.line_count()), vec.rows);
+        let prefix = "     1 fn main() { println!(\"hé";
+        assert_eq!(rope.pos_to_char(1, prefix.len()), rope.line(0).chars().count() + 1 + prefix.chars().count());
+    }
+
+    #[test]
+    #[ignore] // cargo test --release -- --ignored --nocapture
+    fn timing_against_a_vec_of_rows() {
+        for lines in [10_000, 100_000, 1_000_000] {
+            let (_, _, rope_time, vec_time) = run_both(lines, 10_000);
+            println!("{:>9} lines, 10000 edits and a save: rope {:?}, Vec<String> {:?}", lines, rope_time, vec_time);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{env, io};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Where copied text goes besides the editor's own register.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Clipboard {
+    #[default]
+    Auto, // resolved to one of the others on startup
+    Osc52, // asks the terminal to set its clipboard, works over SSH
+    Helper { copy: Vec<String>, paste: Option<Vec<String>> }, // e.g. wl-copy / wl-paste
+    Internal
+}
+
+impl Clipboard {
+    pub fn resolve(self) -> Self {
+        if self != Clipboard::Auto {
+            return self
+        }
+        if env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some() {
+            return Clipboard::Osc52 // a helper would set the remote machine's clipboard
+        }
+        let helper = if env::var_os("WAYLAND_DISPLAY").is_some() {
+            Some(("wl-copy", "wl-paste --no-newline"))
+        } else if env::var_os("DISPLAY").is_some() {
+            Some(("xclip -selection clipboard", "xclip -selection clipboard -o"))
+        } else if cfg!(target_os = "macos") {
+            Some(("pbcopy", "pbpaste"))
+        } else {
+            None
+        };
+        match helper.filter(|(copy, _)| Self::on_path(copy)) {
+            Some((copy, paste)) => Clipboard::Helper {
+                copy: Self::split_command(copy),
+                paste: Some(Self::split_command(paste))
+            },
+            None => Clipboard::Osc52
+        }
+    }
+
+    pub fn split_command(command: &str) -> Vec<String> {
+        command.split_whitespace().map(String::from).collect()
+    }
+
+    fn on_path(command: &str) -> bool {
+        let Some(program) = command.split_whitespace().next() else { return false };
+        env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+    }
+
+    /// Hands `text` to the clipboard; OSC 52 sequences are written to `out`.
+    pub fn copy<W: Write>(&self, text: &str, out: &mut W) -> io::Result<()> {
+        match self {
+            Clipboard::Osc52 => write!(out, "{}", Self::osc52(text)),
+            Clipboard::Helper { copy, .. } => {
+                let (program, args) = copy.split_first().ok_or_else(|| io::Error::other("empty copy command"))?;
+                let mut child = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes())?;
+                }
+                match child.wait()? {
+                    status if status.success() => Ok(()),
+                    status => Err(io::Error::other(format!("{} exited with {}", program, status)))
+                }
+            }
+            Clipboard::Auto | Clipboard::Internal => Ok(())
+        }
+    }
+
+    /// Reads the clipboard, or `None` when only the editor's register is available.
+    pub fn paste(&self) -> Option<String> {
+        let Clipboard::Helper { paste: Some(paste), .. } = self else { return None };
+        let (program, args) = paste.split_first()?;
+        let output = Command::new(program).args(args).stderr(Stdio::null()).output().ok()?;
+        if !output.status.success() {
+            return None
+        }
+        Some(String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n"))
+    }
+
+    pub fn osc52(text: &str) -> String {
+        format!("\x1b]52;c;{}\x07", Self::base64(text.as_bytes()))
+    }
+
+    fn base64(bytes: &[u8]) -> String {
+        let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let group = chunk.iter().enumerate().fold(0u32, |acc, (i, byte)| acc | (*byte as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char)
+                } else {
+                    encoded.push('=')
+                }
+            }
+        }
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osc52_copy_is_padded_base64() {
+        for (text, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("héllo", "aMOpbGxv")] {
+            let mut out = Vec::new();
+            Clipboard::Osc52.copy(text, &mut out).unwrap();
+            assert_eq!(out, format!("\x1b]52;c;{}\x07", encoded).into_bytes());
+        }
+    }
+}
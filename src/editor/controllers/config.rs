@@ -0,0 +1,207 @@
+use crate::editor::controllers::clipboard::Clipboard;
+use crate::editor::controllers::position::WrapMode;
+use std::{env, fs, time};
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+const CONFIG_FILE: &str = "text-magic/config.toml";
+const PROJECT_CONFIG_FILE: &str = ".text-magic.toml";
+
+pub struct Config {
+    pub tab_stop: usize,
+    pub max_quit_attempts: u64,
+    pub message_timeout: time::Duration,
+    pub autosave_idle: time::Duration, // without input before changes are written to the swap file
+    pub help_text: String,
+    pub text_width: Option<usize>,
+    pub wrap_column: Option<usize>,
+    pub centered: bool,
+    pub wrap_mode: WrapMode,
+    pub clipboard: Clipboard,
+    pub keys: Vec<(String, Vec<String>)> // command name to key sequences, checked by the keymap
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_stop: 4,
+            max_quit_attempts: 3,
+            message_timeout: time::Duration::from_secs(5),
+            autosave_idle: time::Duration::from_secs(2),
+            help_text: "Help: Ctrl+S = Save / Ctrl+C = Quit / Ctrl+F = Find / Ctrl+R = Replace \
+                / Ctrl+Z = Undo / Ctrl+Y = Redo / Ctrl+P = All commands".into(),
+            text_width: None,
+            wrap_column: None,
+            centered: false,
+            wrap_mode: WrapMode::Soft,
+            clipboard: Clipboard::Auto,
+            keys: Vec::new()
+        }
+    }
+}
+
+impl Config {
+    /// Loads the user config from the XDG config dir, then the nearest
+    /// `.text-magic.toml` in the working directory or its parents on top of it,
+    /// or only `path` when one is given. Clipboard helper commands are only taken
+    /// from the user config or `path`, a cloned project mustn't run programs.
+    /// Problems are collected rather than fatal so they can be shown in the message bar.
+    pub fn load(path: Option<&Path>) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        if let Some(path) = path {
+            let errors = config.apply_file(path, true);
+            return (config, errors)
+        }
+        let mut errors = Vec::new();
+        for (path, trusted) in [(Self::user_config_path(), true), (Self::project_config_path(), false)] {
+            if let Some(path) = path.filter(|path| path.is_file()) {
+                errors.extend(config.apply_file(&path, trusted));
+            }
+        }
+        (config, errors)
+    }
+
+    fn user_config_path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    fn project_config_path() -> Option<PathBuf> {
+        env::current_dir()
+            .ok()?
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+
+    fn apply_file(&mut self, path: &Path, trusted: bool) -> Vec<String> {
+        let table = match fs::read_to_string(path) {
+            Ok(contents) => match contents.parse::<Table>() {
+                Ok(table) => table,
+                Err(err) => {
+                    let line = err.span().map_or(1, |span| contents[..span.start].matches('\n').count() + 1);
+                    return vec![format!("{} line {}: {}", path.display(), line, err.message().trim())]
+                }
+            },
+            Err(err) => return vec![format!("Couldn't read {}: {}", path.display(), err)]
+        };
+        table
+            .iter()
+            .filter_map(|(key, value)| {
+                self.apply(key, value, trusted).err().map(|err| format!("{}: {}", path.display(), err))
+            })
+            .collect()
+    }
+
+    fn apply(&mut self, key: &str, value: &Value, trusted: bool) -> Result<(), String> {
+        match key {
+            "tab_stop" => self.tab_stop = Self::positive(key, value)?,
+            "max_quit_attempts" => self.max_quit_attempts = Self::positive(key, value)? as u64,
+            "message_timeout" => self.message_timeout = time::Duration::from_secs(Self::positive(key, value)? as u64),
+            "autosave_idle" => self.autosave_idle = time::Duration::from_secs(Self::positive(key, value)? as u64),
+            "help_text" => self.help_text = value
+                .as_str()
+                .ok_or_else(|| format!("'{}' must be a string", key))?
+                .into(),
+            "text_width" => self.text_width = Self::columns(key, value, "full")?,
+            "wrap_column" => self.wrap_column = Self::columns(key, value, "width")?,
+            "centered" => self.centered = value
+                .as_bool()
+                .ok_or_else(|| format!("'{}' must be true or false", key))?,
+            "wrap_mode" => self.wrap_mode = match value.as_str() {
+                Some("soft") => WrapMode::Soft,
+                Some("hard") => WrapMode::Hard,
+                _ => return Err(format!("'{}' must be \"soft\" or \"hard\"", key))
+            },
+            "clipboard" => self.clipboard = match value {
+                Value::String(name) if name == "auto" => Clipboard::Auto,
+                Value::String(name) if name == "osc52" => Clipboard::Osc52,
+                Value::String(name) if name == "internal" => Clipboard::Internal,
+                Value::Table(_) if !trusted => {
+                    return Err("clipboard commands are only read from the user config, not project files".into())
+                }
+                Value::Table(table) => {
+                    let command = |name: &str| match table.get(name) {
+                        None => Ok(None),
+                        Some(Value::String(command)) if !command.trim().is_empty() => Ok(Some(Clipboard::split_command(command))),
+                        Some(_) => Err(format!("clipboard.{} must be a command line", name))
+                    };
+                    Clipboard::Helper {
+                        copy: command("copy")?.ok_or("clipboard.copy is required")?,
+                        paste: command("paste")?
+                    }
+                }
+                _ => return Err(format!(
+                    "'{}' must be \"auto\", \"osc52\", \"internal\" or a table with copy / paste commands", key
+                ))
+            },
+            "keys" => {
+                let table = value.as_table().ok_or_else(|| format!("'{}' must be a table", key))?;
+                for (command, keys) in table {
+                    let keys = match keys {
+                        Value::String(keys) => vec![keys.clone()],
+                        Value::Array(list) => list
+                            .iter()
+                            .map(|it| it.as_str().map(String::from))
+                            .collect::<Option<_>>()
+                            .ok_or_else(|| format!("keys.{} must be a string or a list of strings", command))?,
+                        _ => return Err(format!("keys.{} must be a string or a list of strings", command))
+                    };
+                    self.keys.push((command.clone(), keys))
+                }
+            }
+            _ => return Err(format!("unknown key '{}'", key))
+        }
+        Ok(())
+    }
+
+    fn positive(key: &str, value: &Value) -> Result<usize, String> {
+        value
+            .as_integer()
+            .filter(|it| *it > 0)
+            .map(|it| it as usize)
+            .ok_or_else(|| format!("'{}' must be a positive integer", key))
+    }
+
+    fn columns(key: &str, value: &Value, unset: &str) -> Result<Option<usize>, String> {
+        if value.as_str() == Some(unset) {
+            return Ok(None)
+        }
+        value
+            .as_integer()
+            .filter(|it| *it >= 10)
+            .map(|it| Some(it as usize))
+            .ok_or_else(|| format!("'{}' must be at least 10 columns or \"{}\"", key, unset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipboard_helper_only_from_trusted_files() {
+        let helper: Table = r#"clipboard = { copy = "touch pwned" }"#.parse().unwrap();
+        let mut config = Config::default();
+        assert!(config.apply("clipboard", &helper["clipboard"], false).is_err());
+        assert_eq!(config.clipboard, Clipboard::Auto);
+        config.apply("clipboard", &helper["clipboard"], true).unwrap();
+        assert_eq!(config.clipboard, Clipboard::Helper { copy: vec!["touch".into(), "pwned".into()], paste: None });
+        config.apply("clipboard", &Value::String("osc52".into()), false).unwrap();
+        assert_eq!(config.clipboard, Clipboard::Osc52);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let path = env::temp_dir().join(format!("tm-config-test-{}.toml", std::process::id()));
+        for (contents, line) in [("tab_stop =", 1), ("tab_stop = 4\n\nwrap_mode = \n", 3), ("\n\n[keys\n", 3)] {
+            fs::write(&path, contents).unwrap();
+            let errors = Config::default().apply_file(&path, true);
+            assert!(errors[0].starts_with(&format!("{} line {}:", path.display(), line)), "{:?}", errors);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
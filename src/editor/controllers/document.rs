@@ -0,0 +1,30 @@
+use crate::editor::controllers::file::FileController;
+use crate::editor::controllers::highlight::SyntaxController;
+use crate::editor::controllers::history::HistoryController;
+use crate::editor::controllers::position::View;
+
+/// One open file with its own history and view. The document being edited
+/// lives on `Controllers` itself, the rest wait here until switched to.
+#[derive(Default)]
+pub struct Document {
+    pub file_ctrlr: FileController,
+    pub history: HistoryController,
+    pub syntax: SyntaxController,
+    pub dirty: u64,
+    pub view: View
+}
+
+impl Document {
+    pub fn new(file_ctrlr: FileController) -> Self {
+        Self {
+            file_ctrlr,
+            ..Default::default()
+        }
+    }
+
+    pub fn name(file_ctrlr: &FileController) -> String {
+        file_ctrlr.filename
+            .as_ref()
+            .map_or_else(|| "[No Name]".into(), |path| path.display().to_string())
+    }
+}
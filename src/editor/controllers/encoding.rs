@@ -0,0 +1,264 @@
+use std::borrow::Cow;
+use std::{fmt, io};
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16BE_BOM: &[u8] = &[0xfe, 0xff];
+const SNIFF_BYTES: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1 // what's left when the bytes aren't valid UTF-8, every byte is a character
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr
+}
+
+impl LineEnding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r"
+        }
+    }
+}
+
+/// How a file's text is stored on disk, so saving writes it back the way it was read.
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub line_ending: LineEnding,
+    pub mixed_line_endings: bool, // others than `line_ending` were read too, saving makes them all the same
+    pub final_newline: bool
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self { encoding: Encoding::Utf8, bom: false, line_ending: LineEnding::Lf, mixed_line_endings: false, final_newline: true }
+    }
+}
+
+impl fmt::Display for FileFormat { // for the status bar, e.g. "UTF-16LE BOM CRLF (mixed)"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let encoding = match self.encoding {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1"
+        };
+        let line_ending = match self.line_ending {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR"
+        };
+        write!(
+            f,
+            "{}{} {}{}{}",
+            encoding,
+            if self.bom { " BOM" } else { "" },
+            line_ending,
+            if self.mixed_line_endings { " (mixed)" } else { "" },
+            if self.final_newline { "" } else { " noeol" }
+        )
+    }
+}
+
+impl FileFormat {
+    /// Works out the format of `bytes` and returns it with the rows of text.
+    pub fn decode(bytes: &[u8]) -> (Self, Vec<String>) {
+        let (encoding, bom) = if bytes.starts_with(UTF8_BOM) {
+            (Encoding::Utf8, true)
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            (Encoding::Utf16Le, true)
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            (Encoding::Utf16Be, true)
+        } else if let Some(encoding) = Self::sniff_utf16(bytes) {
+            (encoding, false)
+        } else if std::str::from_utf8(bytes).is_ok() {
+            (Encoding::Utf8, false)
+        } else {
+            (Encoding::Latin1, false)
+        };
+        let body = if bom { &bytes[if encoding == Encoding::Utf8 { 3 } else { 2 }..] } else { bytes };
+        let text = match encoding {
+            Encoding::Utf8 => std::str::from_utf8(body).ok().map(String::from),
+            Encoding::Utf16Le | Encoding::Utf16Be => Self::decode_utf16(body, encoding),
+            Encoding::Latin1 => Some(Self::decode_latin1(body))
+        };
+        let (encoding, bom, text) = match text {
+            Some(text) => (encoding, bom, text),
+            None => (Encoding::Latin1, false, Self::decode_latin1(bytes)) // a BOM on something else, keep every byte
+        };
+        let (line_ending, mixed_line_endings) = Self::majority_line_ending(&text);
+        let separator = if line_ending == LineEnding::Cr { '\r' } else { '\n' };
+        let final_newline = text.is_empty() || text.ends_with(separator);
+        let mut rows: Vec<String> = text
+            .split(separator)
+            .map(|row| row.strip_suffix('\r').filter(|_| separator == '\n').unwrap_or(row).to_string())
+            .collect();
+        if final_newline {
+            rows.pop(); // the empty piece after the last line break
+        }
+        (Self { encoding, bom, line_ending, mixed_line_endings, final_newline }, rows)
+    }
+
+    fn decode_utf16(body: &[u8], encoding: Encoding) -> Option<String> { // None unless every byte is part of a character
+        if !body.len().is_multiple_of(2) {
+            return None
+        }
+        let units: Vec<u16> = body
+            .chunks_exact(2)
+            .map(|pair| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                _ => u16::from_be_bytes([pair[0], pair[1]])
+            })
+            .collect();
+        String::from_utf16(&units).ok()
+    }
+
+    fn decode_latin1(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| *byte as char).collect()
+    }
+
+    fn sniff_utf16(bytes: &[u8]) -> Option<Encoding> { // ASCII heavy UTF-16 has a zero in every other byte
+        let sample = &bytes[..bytes.len().min(SNIFF_BYTES)];
+        if sample.len() < 2 || !bytes.len().is_multiple_of(2) || !sample.contains(&0) {
+            return None
+        }
+        let pairs = sample.len() / 2;
+        let zeros = |offset: usize| sample.iter().skip(offset).step_by(2).filter(|byte| **byte == 0).count();
+        match (zeros(0), zeros(1)) {
+            (even, odd) if odd * 10 >= pairs * 4 && even * 10 < pairs => Some(Encoding::Utf16Le),
+            (even, odd) if even * 10 >= pairs * 4 && odd * 10 < pairs => Some(Encoding::Utf16Be),
+            _ => None
+        }
+    }
+
+    fn majority_line_ending(text: &str) -> (LineEnding, bool) { // mixed files are saved with the most common one
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        let cr = text.matches('\r').count() - crlf;
+        let mixed = [crlf, lf, cr].iter().filter(|count| **count > 0).count() > 1;
+        let line_ending = if crlf > lf && crlf >= cr {
+            LineEnding::CrLf
+        } else if cr > lf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        };
+        (line_ending, mixed)
+    }
+
+    pub fn bom_bytes(&self) -> &'static [u8] {
+        match (self.bom, self.encoding) {
+            (false, _) | (_, Encoding::Latin1) => &[],
+            (true, Encoding::Utf8) => UTF8_BOM,
+            (true, Encoding::Utf16Le) => UTF16LE_BOM,
+            (true, Encoding::Utf16Be) => UTF16BE_BOM
+        }
+    }
+
+    /// Encodes text whose rows end in `\n`, fails on characters the encoding can't hold.
+    pub fn encode<'a>(&self, text: &'a str) -> io::Result<Cow<'a, [u8]>> {
+        let text = match self.line_ending {
+            LineEnding::Lf => Cow::Borrowed(text),
+            ending => Cow::Owned(text.replace('\n', ending.as_str()))
+        };
+        Ok(match self.encoding {
+            Encoding::Utf8 => match text {
+                Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes())
+            },
+            Encoding::Utf16Le => Cow::Owned(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Cow::Owned(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 => Cow::Owned(
+                text.chars()
+                    .map(|ch| u8::try_from(ch).map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("'{}' can't be saved as Latin-1", ch))
+                    }))
+                    .collect::<io::Result<_>>()?
+            )
+        })
+    }
+
+    pub fn parse_encoding(&mut self, name: &str) -> Result<(), String> {
+        (self.encoding, self.bom) = match name.trim().to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => (Encoding::Utf8, false),
+            "utf-8-bom" | "utf8-bom" => (Encoding::Utf8, true),
+            "utf-16le" | "utf-16" => (Encoding::Utf16Le, true),
+            "utf-16be" => (Encoding::Utf16Be, true),
+            "latin-1" | "latin1" | "iso-8859-1" => (Encoding::Latin1, false),
+            _ => return Err(format!("Unknown encoding '{}', use utf-8, utf-8-bom, utf-16le, utf-16be or latin-1", name))
+        };
+        Ok(())
+    }
+
+    pub fn parse_line_ending(&mut self, name: &str) -> Result<(), String> {
+        self.mixed_line_endings = false;
+        self.line_ending = match name.trim().to_lowercase().as_str() {
+            "lf" | "unix" => LineEnding::Lf,
+            "crlf" | "dos" | "windows" => LineEnding::CrLf,
+            "cr" | "mac" => LineEnding::Cr,
+            _ => return Err(format!("Unknown line ending '{}', use lf, crlf or cr", name))
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> (FileFormat, Vec<u8>) {
+        let (format, rows) = FileFormat::decode(bytes);
+        let text: String = rows.iter().map(|row| format!("{}\n", row)).collect();
+        let text = if format.final_newline { &text[..] } else { &text[..text.len().saturating_sub(1)] };
+        let mut saved = format.bom_bytes().to_vec();
+        saved.extend_from_slice(&format.encode(text).unwrap());
+        (format, saved)
+    }
+
+    #[test]
+    fn utf16_with_a_bom() {
+        let bytes = b"\xff\xfeh\x00i\x00\r\x00\n\x00";
+        let (format, saved) = round_trip(bytes);
+        assert_eq!(format.to_string(), "UTF-16LE BOM CRLF");
+        assert_eq!(saved, bytes);
+    }
+
+    #[test]
+    fn broken_utf16_falls_back_to_latin1() {
+        for bytes in [
+            &b"\xff\xfeh\x00i\x00\n"[..], // trailing odd byte
+            &b"\xfe\xff\x00h\xd8\x00\x00\n"[..], // unpaired high surrogate
+            &b"\xff\xfe\x00\xdch\x00\n\x00"[..] // unpaired low surrogate
+        ] {
+            let (format, saved) = round_trip(bytes);
+            assert!(format.encoding == Encoding::Latin1 && !format.bom);
+            assert_eq!(saved, bytes);
+        }
+        let (format, saved) = round_trip(b"\xef\xbb\xbfcaf\xe9\n"); // a UTF-8 BOM on Latin-1 text
+        assert!(format.encoding == Encoding::Latin1);
+        assert_eq!(saved, b"\xef\xbb\xbfcaf\xe9\n");
+    }
+
+    #[test]
+    fn mixed_line_endings_are_flagged() {
+        let (format, _) = FileFormat::decode(b"a\r\nb\r\nc\n");
+        assert_eq!(format.to_string(), "UTF-8 CRLF (mixed)");
+        let (mut format, _) = FileFormat::decode(b"a\r\nb\r\n");
+        assert_eq!(format.to_string(), "UTF-8 CRLF");
+        format.mixed_line_endings = true;
+        format.parse_line_ending("crlf").unwrap();
+        assert!(!format.mixed_line_endings);
+    }
+}
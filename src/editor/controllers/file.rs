@@ -1,156 +1,552 @@
-use std::{env, fs};
-use std::io::{self, Write};
+use crate::editor::controllers::buffer::TextBuffer;
+use crate::editor::controllers::config::Config;
+use crate::editor::controllers::encoding::FileFormat;
+use crate::editor::controllers::swap::SwapState;
+use ignore::WalkBuilder;
+use std::{cmp, fs, process, time};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufWriter, Read};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-const TAB_STOP: usize = 4;
+const PREVIEW_BYTES: u64 = 64 * 1024;
 
-pub struct Row {
-    pub content: String,
-    pub render: String
+/// The file on disk as we last saw it, to notice when something else changes it.
+#[derive(Clone, Copy, PartialEq)]
+struct DiskSnapshot {
+    modified: Option<time::SystemTime>,
+    len: u64,
+    hash: u64 // only compared when the cheap fields differ, so touching a file isn't a change
 }
 
-impl Default for Row {
-    fn default() -> Self {
-        Self::new(String::new(), String::new())
-    }
-}
-
-impl Row {
-    fn new(content: String, render: String) -> Self {
-        Self {
-            content,
-            render
-        }
-    }
-
-    pub fn len(&self) -> usize {
-        self.content.len()
+impl DiskSnapshot {
+    fn new(metadata: &fs::Metadata, bytes: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        Self { modified: metadata.modified().ok(), len: metadata.len(), hash: hasher.finish() }
     }
 
-    pub fn insert_char(&mut self, idx: usize, ch: char) {
-        self.content.insert(idx, ch);
-        FileController::render_row(self);
-    }
-
-    pub fn delete_char(&mut self, idx: usize) {
-        self.content.remove(idx);
-        FileController::render_row(self);
+    fn same_stamp(&self, metadata: &fs::Metadata) -> bool {
+        self.modified == metadata.modified().ok() && self.len == metadata.len()
     }
 }
 
 pub struct FileController {
-    rows: Vec<Row>,
-    pub filename: Option<PathBuf>
+    buffer: TextBuffer,
+    changed_from: Option<usize>,
+    tab_stop: usize,
+    pub filename: Option<PathBuf>,
+    pub swap: SwapState,
+    pub format: FileFormat,
+    disk: Option<DiskSnapshot>, // None until the file exists
+    base: TextBuffer, // the text on disk at that point, the common ancestor for merges, shares the rope's chunks
+    external: Option<(DiskSnapshot, Vec<String>)> // a newer version on disk the user hasn't dealt with
 }
 
 impl Default for FileController {
     fn default() -> Self {
-        Self::new()
+        Self::new(Config::default().tab_stop)
     }
 }
 
 impl FileController {
-    pub fn new() -> Self {
-        let mut arg = env::args();
-
-        match arg.nth(1) {
-            None => Self {
-                rows: Vec::new(),
-                filename: None
-            },
-            Some(file) => Self::from_file(file.into())
+    pub fn new(tab_stop: usize) -> Self {
+        Self {
+            buffer: TextBuffer::new(),
+            changed_from: None,
+            tab_stop,
+            filename: None,
+            swap: SwapState::Clean,
+            format: FileFormat::default(),
+            disk: None,
+            base: TextBuffer::new(),
+            external: None
         }
     }
 
-    pub fn render_row(row: &mut Row) {
+    pub fn render_row(content: &str, tab_stop: usize) -> Vec<String> { // one rendered cell per grapheme
         let mut index = 0;
-        let capacity = row
-            .content
-            .chars()
-            .fold(0, |acc, next| acc + (if next == '\t' { 8 } else { 1 }));
-        row.render = String::with_capacity(capacity);
-        row.content.chars().for_each(|c| {
-            index += 1;
-            if c == '\t' {
-                row.render.push(' ');
-                while index % TAB_STOP != 0 {
-                    row.render.push(' ');
+        content.graphemes(true).map(|g| {
+            if g == "\t" {
+                let mut cell = String::from(" ");
+                index += 1;
+                while index % tab_stop != 0 {
+                    cell.push(' ');
                     index += 1
                 }
+                cell
+            } else if let Some(cell) = Self::control_cell(g) {
+                index += cell.width();
+                cell
             } else {
-                row.render.push(c)
+                index += g.width();
+                g.to_string()
             }
-        })
+        }).collect()
     }
 
-    fn from_file(file: PathBuf) -> Self {
-        let fp: &Path = file.as_path();
-        let file_contents = if fp.exists() {
-            fs::read_to_string(&file).expect("Failed to read file")                
-                .lines()
-                .map(|it| {
-                    let mut row = Row::new(it.into(), String::new());
-                    Self::render_row(&mut row);
-                    row
-                })
-                .collect()
+    fn control_cell(g: &str) -> Option<String> { // shown as ^X or U+FFFD rather than run by the terminal
+        let ch = g.chars().next()?;
+        match ch {
+            '\0'..='\x1f' => Some(format!("^{}", (ch as u8 + 0x40) as char)),
+            '\x7f' => Some("^?".into()),
+            '\u{80}'..='\u{9f}' => Some("\u{fffd}".into()),
+            _ => None
+        }
+    }
+
+    pub fn render_slice(render: &str, start: usize, width: usize) -> String { // by display columns
+        let mut col = 0;
+        let mut slice = String::with_capacity(width);
+        for g in render.graphemes(true) {
+            let cell = Self::control_cell(g);
+            let g = cell.as_deref().unwrap_or(g);
+            let end = col + g.width();
+            if col >= start + width {
+                break
+            } else if col >= start && end <= start + width {
+                slice.push_str(g)
+            } else if end > start { // wide character cut by the edge of the screen
+                (cmp::max(col, start)..cmp::min(end, start + width)).for_each(|_| slice.push(' '))
+            }
+            col = end;
+        }
+        slice
+    }
+
+    pub fn grapheme_count(s: &str) -> usize {
+        s.graphemes(true).count()
+    }
+
+    pub fn byte_index(s: &str, grapheme_idx: usize) -> usize {
+        s.grapheme_indices(true)
+            .nth(grapheme_idx)
+            .map_or(s.len(), |(idx, _)| idx)
+    }
+
+    pub fn grapheme_range(s: &str, bytes: Range<usize>) -> Range<usize> { // every cluster the bytes touch
+        let starts: Vec<usize> = s.grapheme_indices(true).map(|(idx, _)| idx).collect();
+        let start = if bytes.start < s.len() {
+            starts.partition_point(|idx| *idx <= bytes.start) - 1
         } else {
-            Vec::new()
+            starts.len()
         };
+        start..cmp::max(start, starts.partition_point(|idx| *idx < bytes.end))
+    }
+
+    pub fn open(file: PathBuf, tab_stop: usize) -> io::Result<Self> { // a missing file starts out empty
+        let (disk, format, rows) = match Self::read_disk(&file) {
+            Ok((disk, format, rows)) => (Some(disk), format, rows),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (None, FileFormat::default(), Vec::new()),
+            Err(err) => return Err(err)
+        };
+        let base = TextBuffer::from_lines(rows.iter().map(String::as_str));
+        Ok(Self {
+            buffer: base.clone(),
+            changed_from: None,
+            tab_stop,
+            swap: SwapState::detect(Some(&file)),
+            filename: Some(file),
+            format,
+            disk,
+            base,
+            external: None
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8], tab_stop: usize) -> Self { // unnamed, e.g. read from stdin
+        let (format, rows) = FileFormat::decode(bytes);
         Self {
-            rows: file_contents,
-            filename: Some(file)
+            buffer: TextBuffer::from_lines(rows.iter().map(String::as_str)),
+            format,
+            swap: SwapState::detect(None), // the unnamed swap may belong to another session
+            ..Self::new(tab_stop)
         }
     }
 
-    pub fn get_render(&self, idx: usize) -> &String {
-        &self.rows[idx].render
+    fn read_disk(path: &Path) -> io::Result<(DiskSnapshot, FileFormat, Vec<String>)> {
+        let metadata = fs::metadata(path)?;
+        let bytes = fs::read(path)?;
+        let (format, rows) = FileFormat::decode(&bytes);
+        Ok((DiskSnapshot::new(&metadata, &bytes), format, rows))
+    }
+
+    pub fn read_rows(path: &Path) -> io::Result<Vec<String>> {
+        Ok(FileFormat::decode(&fs::read(path)?).1)
+    }
+
+    /// Looks for a version on disk other than the one loaded or saved last, which
+    /// is then kept until `take_external` deals with it. Only reports it once.
+    pub fn check_disk(&mut self) -> io::Result<bool> {
+        let Some(filename) = &self.filename else { return Ok(false) };
+        let Ok(metadata) = fs::metadata(filename) else { return Ok(false) }; // gone, saving writes it again
+        match (&self.external, &self.disk) {
+            (Some((seen, _)), _) | (None, Some(seen)) if seen.same_stamp(&metadata) => return Ok(false),
+            _ => {}
+        }
+        let (disk, _, rows) = Self::read_disk(filename)?;
+        if self.disk.is_some_and(|known| known.hash == disk.hash) {
+            self.disk = Some(disk);
+            self.external = None;
+            return Ok(false)
+        }
+        self.external = Some((disk, rows));
+        Ok(true)
+    }
+
+    pub fn has_external(&self) -> bool {
+        self.external.is_some()
+    }
+
+    pub fn base(&self) -> Vec<String> {
+        self.base.lines(0..self.base.line_count())
+    }
+
+    /// Accepts the newer version on disk as the one that's known, returning its rows.
+    pub fn take_external(&mut self) -> Option<Vec<String>> {
+        let (disk, rows) = self.external.take()?;
+        self.disk = Some(disk);
+        self.base = TextBuffer::from_lines(rows.iter().map(String::as_str));
+        Some(rows)
+    }
+
+    pub fn swap_path(&self) -> PathBuf {
+        SwapState::path(self.filename.as_deref())
+    }
+
+    /// Brings the swap file in line with `dirty`, the document's edit count.
+    /// Swaps left behind by another session are never touched.
+    pub fn update_swap(&mut self, dirty: u64) -> io::Result<()> {
+        match (self.swap, dirty) {
+            (SwapState::Found | SwapState::Deferred, _) | (SwapState::Clean, 0) => Ok(()),
+            (SwapState::Written(_) | SwapState::Failed(_), 0) => self.remove_swap(),
+            (SwapState::Written(written) | SwapState::Failed(written), _) if written == dirty => Ok(()),
+            _ => {
+                // only for us to read, and UTF-8 so every character typed fits whatever the file's encoding
+                let written = self.write_file(&self.swap_path(), &FileFormat::default(), true);
+                self.swap = if written.is_ok() { SwapState::Written(dirty) } else { SwapState::Failed(dirty) };
+                written.map(|_| ())
+            }
+        }
+    }
+
+    pub fn remove_swap(&mut self) -> io::Result<()> {
+        self.swap = SwapState::Clean;
+        match fs::remove_file(self.swap_path()) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(())
+        }
+    }
+
+    pub fn project_files(limit: usize) -> Vec<String> {
+        let mut files: Vec<String> = WalkBuilder::new(".")
+            .require_git(false)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|it| it.is_file()))
+            .filter_map(|entry| entry.path().strip_prefix(".").ok()?.to_str().map(String::from))
+            .take(limit)
+            .collect();
+        files.sort_by_key(|path| (!path.ends_with(".md"), path.clone()));
+        files
+    }
+
+    pub fn preview(path: &Path, lines: usize) -> Vec<String> { // the first lines, without reading the whole file
+        let mut bytes = Vec::new();
+        let read = fs::File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+        match read {
+            Err(err) => vec![format!("(can't preview: {})", err)],
+            Ok(_) if bytes.contains(&0) => vec!["(binary file)".into()],
+            Ok(_) => String::from_utf8_lossy(&bytes).lines().take(lines).map(String::from).collect()
+        }
+    }
+
+    fn mark_changed(&mut self, row_idx: usize) {
+        self.changed_from = Some(self.changed_from.map_or(row_idx, |row| cmp::min(row, row_idx)))
+    }
+
+    pub fn take_changed_from(&mut self) -> Option<usize> { // first row edited since the last call
+        self.changed_from.take()
+    }
+
+    pub fn get_render(&self, idx: usize) -> Vec<String> {
+        Self::render_row(&self.buffer.line(idx), self.tab_stop)
     }
 
     pub fn count_rows(&self) -> usize {
-        self.rows.len()
+        self.buffer.line_count()
     }
 
-    pub fn get_editor_row(&self, idx: usize) -> &Row {
-        &self.rows[idx]
+    pub fn get_editor_row(&self, idx: usize) -> String {
+        self.buffer.line(idx)
     }
 
-    pub fn get_editor_row_mut(&mut self, idx: usize) -> &mut Row {
-        &mut self.rows[idx]
+    pub fn row_len(&self, idx: usize) -> usize { // in grapheme clusters
+        Self::grapheme_count(&self.buffer.line(idx))
+    }
+
+    /// Inserts `ch` before the grapheme at `at` and returns the cursor position
+    /// after it, which doesn't advance when `ch` combines with the previous cluster.
+    pub fn insert_char(&mut self, row_idx: usize, at: usize, ch: char) -> usize {
+        let mut row = self.buffer.line(row_idx);
+        let byte_idx = Self::byte_index(&row, at);
+        self.buffer.insert(row_idx, byte_idx, ch.encode_utf8(&mut [0; 4]));
+        row.insert(byte_idx, ch);
+        self.mark_changed(row_idx);
+        Self::grapheme_count(&row[..byte_idx + ch.len_utf8()])
+    }
+
+    pub fn delete_char(&mut self, row_idx: usize, at: usize) {
+        let row = self.buffer.line(row_idx);
+        let start = Self::byte_index(&row, at);
+        let end = Self::byte_index(&row, at + 1);
+        self.buffer.remove(row_idx, start..end);
+        self.mark_changed(row_idx)
+    }
+
+    pub fn split_row(&mut self, row_idx: usize, at: usize) {
+        let byte_idx = Self::byte_index(&self.buffer.line(row_idx), at);
+        self.buffer.insert(row_idx, byte_idx, "\n");
+        self.mark_changed(row_idx)
     }
 
     pub fn join_adjacent_rows(&mut self, row_idx: usize) {
         if row_idx == 0 { return }
-        let curr_row = self.rows.remove(row_idx);
-        let prev_row = self.get_editor_row_mut(row_idx - 1);
-        prev_row.content.push_str(&curr_row.content);
-        Self::render_row(prev_row);
+        self.buffer.join_lines(row_idx);
+        self.mark_changed(row_idx - 1)
+    }
+
+    pub fn row_contents(&self, row_idx: usize, count: usize) -> Vec<String> {
+        self.buffer.lines(row_idx..row_idx + count)
+    }
+
+    pub fn replace_rows(&mut self, row_idx: usize, count: usize, contents: Vec<String>) {
+        self.buffer.replace_lines(row_idx, count, &contents);
+        self.mark_changed(row_idx)
     }
 
     pub fn insert_row(&mut self, row_idx: usize, content: String) {
-        let mut new_row = Row::new(content, String::new());
-        Self::render_row(&mut new_row);
-        self.rows.insert(row_idx, new_row);
-    }
-
-    pub fn save_file(&self, filename: &PathBuf) -> io::Result<usize> {
-        let mut file = fs::OpenOptions::new().write(true).create(true).open(filename)?;
-        let contents: String = self.rows
-            .iter()
-            .map(|it| it.content.as_str())
-            .collect::<Vec<&str>>()
-            .join("\n");
-        file.set_len(contents.len() as u64)?;
-        file.write_all(contents.as_bytes())?;
-        Ok(contents.as_bytes().len())
-    }
-
-    pub fn save(&self) -> io::Result<usize> {
-        match &self.filename {
-            None => Err(io::Error::new(io::ErrorKind::Other, "No filename specified")),
-            Some(name) => {
-                self.save_file(name)
+        self.buffer.insert_line(row_idx, &content);
+        self.mark_changed(row_idx)
+    }
+
+    /// Writes a temporary file next to the target, syncs it and renames it over
+    /// the target, so the file on disk is either the old or the new version.
+    /// Saving through a symlink replaces the file it points to.
+    pub fn save_file(&self, filename: &Path) -> io::Result<usize> {
+        self.write_file(filename, &self.format, false)
+    }
+
+    /// Like `save_file`, a `private` file is only readable by its owner rather than
+    /// taking the permissions of the file it replaces.
+    fn write_file(&self, filename: &Path, format: &FileFormat, private: bool) -> io::Result<usize> {
+        let target = Self::resolve_symlinks(filename)?;
+        if !private && target.exists() {
+            // renaming over it would work as long as the directory is writable, but the file is read-only for a reason
+            let writable = !fs::metadata(&target)?.permissions().readonly()
+                && fs::OpenOptions::new().write(true).open(&target).is_ok();
+            if !writable {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "the file is read-only"))
+            }
+        }
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+        let name = target.file_name().ok_or_else(|| io::Error::other("Not a file name"))?;
+        let stamp = time::SystemTime::now().duration_since(time::UNIX_EPOCH).map_or(0, |it| it.subsec_nanos());
+        let temp = dir.join(format!(".{}.{}-{}.tm-save", name.to_string_lossy(), process::id(), stamp));
+        let written = self.write_temp(&temp, &target, format, private).and_then(|len| match len {
+            Some(len) => fs::rename(&temp, &target).map(|_| len),
+            None => { // replacing it would hand the file over to us
+                let _ = fs::remove_file(&temp);
+                self.write_in_place(&target, format)
+            }
+        });
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        } else if let Ok(dir) = fs::File::open(&dir) {
+            let _ = dir.sync_all(); // make the rename itself durable, not supported everywhere
+        }
+        written
+    }
+
+    /// Returns `None` without writing when the temporary file can't be given the target's owner.
+    fn write_temp(&self, temp: &Path, target: &Path, format: &FileFormat, private: bool) -> io::Result<Option<usize>> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(temp)?;
+        if let Some(metadata) = fs::metadata(target).ok().filter(|_| !private) {
+            file.set_permissions(metadata.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                if std::os::unix::fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+                    return Ok(None) // only root can give files away
+                }
+            }
+        }
+        let len = self.buffer.write_to(BufWriter::new(&file), format)?;
+        file.sync_all()?;
+        Ok(Some(len))
+    }
+
+    fn write_in_place(&self, target: &Path, format: &FileFormat) -> io::Result<usize> { // keeps the owner, but isn't atomic
+        let file = fs::OpenOptions::new().write(true).truncate(true).open(target)?;
+        let len = self.buffer.write_to(BufWriter::new(&file), format)?;
+        file.sync_all()?;
+        Ok(len)
+    }
+
+    fn resolve_symlinks(filename: &Path) -> io::Result<PathBuf> { // the target even if it doesn't exist yet
+        let mut path = filename.to_path_buf();
+        for _ in 0..40 {
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    let link = fs::read_link(&path)?;
+                    path = path.parent().map_or(link.clone(), |dir| dir.join(&link));
+                }
+                _ => return Ok(path)
             }
         }
+        Err(io::Error::other("Too many levels of symbolic links"))
     }
-}
\ No newline at end of file
+
+    pub fn save(&mut self) -> io::Result<usize> {
+        let Some(name) = &self.filename else { return Err(io::Error::other("No filename specified")) };
+        let len = self.save_file(name)?;
+        self.format.mixed_line_endings = false; // all written with `line_ending`
+        self.base = self.buffer.clone();
+        self.disk = Self::read_disk(name).ok().map(|(disk, ..)| disk);
+        self.external = None;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &str = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}"; // one cluster of five chars
+
+    fn with_row(row: &str) -> FileController {
+        FileController::from_bytes(format!("{}\n", row).as_bytes(), 4)
+    }
+
+    #[test]
+    fn byte_index_counts_clusters() {
+        assert_eq!(FileController::byte_index("héllo", 2), 3);
+        assert_eq!(FileController::byte_index("héllo", 9), "héllo".len());
+        assert_eq!(FileController::byte_index("e\u{301}x", 1), 3);
+        assert_eq!(FileController::byte_index(&format!("{}x", FAMILY), 1), FAMILY.len());
+        assert_eq!(FileController::byte_index("日本語", 2), 6);
+    }
+
+    #[test]
+    fn grapheme_range_rounds_outwards() {
+        assert_eq!(FileController::grapheme_range("cafe\u{301}", 4..6), 3..4); // only the accent
+        assert_eq!(FileController::grapheme_range("éé foo", 5..8), 3..6);
+        assert_eq!(FileController::grapheme_range("éé foo", 1..3), 0..2);
+        assert_eq!(FileController::grapheme_range("éé", 2..2), 1..1);
+        assert_eq!(FileController::grapheme_range("éé", 4..4), 2..2);
+    }
+
+    #[test]
+    fn insert_char_returns_the_cursor_after_it() {
+        let mut file_ctrlr = with_row("abc");
+        assert_eq!(file_ctrlr.insert_char(0, 1, 'é'), 2);
+        assert_eq!(file_ctrlr.get_editor_row(0), "aébc");
+        assert_eq!(file_ctrlr.insert_char(0, 0, '日'), 1);
+        assert_eq!(file_ctrlr.get_editor_row(0), "日aébc");
+
+        let mut file_ctrlr = with_row("ab");
+        assert_eq!(file_ctrlr.insert_char(0, 1, '\u{301}'), 1); // combines with the 'a'
+        assert_eq!(file_ctrlr.get_editor_row(0), "a\u{301}b");
+        assert_eq!(file_ctrlr.row_len(0), 2);
+
+        let mut file_ctrlr = with_row("\u{1f468}x");
+        assert_eq!(file_ctrlr.insert_char(0, 1, '\u{200d}'), 1);
+        assert_eq!(file_ctrlr.insert_char(0, 1, '\u{1f469}'), 1);
+        assert_eq!(file_ctrlr.insert_char(0, 1, '\u{200d}'), 1);
+        assert_eq!(file_ctrlr.insert_char(0, 1, '\u{1f467}'), 1);
+        assert_eq!(file_ctrlr.get_editor_row(0), format!("{}x", FAMILY));
+        assert_eq!(file_ctrlr.row_len(0), 2);
+    }
+
+    #[test]
+    fn delete_char_removes_whole_clusters() {
+        let mut file_ctrlr = with_row(&format!("ae\u{301}{}日本", FAMILY));
+        file_ctrlr.delete_char(0, 1);
+        assert_eq!(file_ctrlr.get_editor_row(0), format!("a{}日本", FAMILY));
+        file_ctrlr.delete_char(0, 1);
+        assert_eq!(file_ctrlr.get_editor_row(0), "a日本");
+        file_ctrlr.delete_char(0, 1);
+        assert_eq!(file_ctrlr.get_editor_row(0), "a本");
+        file_ctrlr.delete_char(0, 2); // past the end
+        assert_eq!(file_ctrlr.get_editor_row(0), "a本");
+    }
+
+    #[test]
+    fn render_row_has_a_cell_per_cluster() {
+        assert_eq!(FileController::render_row("a\tb", 4), ["a", "   ", "b"]);
+        assert_eq!(FileController::render_row("日\tb", 4), ["日", "  ", "b"]);
+        assert_eq!(FileController::render_row("e\u{301}\t", 4), ["e\u{301}", "   "]);
+        assert_eq!(FileController::render_row(&format!("{}!", FAMILY), 4), [FAMILY, "!"]);
+    }
+
+    #[test]
+    fn control_characters_get_visible_cells() {
+        assert_eq!(
+            FileController::render_row("a\rb\x1b[1m\u{85}\x7f\t", 4),
+            ["a", "^M", "b", "^[", "[", "1", "m", "\u{fffd}", "^?", "    "]
+        );
+        assert_eq!(FileController::render_slice("a\x1bb\u{9b}", 0, 10), "a^[b\u{fffd}");
+        let file_ctrlr = FileController::from_bytes(b"a\rb\nc\n", 4);
+        assert_eq!(file_ctrlr.get_render(0).concat(), "a^Mb");
+    }
+
+    #[test]
+    fn render_slice_pads_cut_wide_characters() {
+        assert_eq!(FileController::render_slice("日本語", 0, 6), "日本語");
+        assert_eq!(FileController::render_slice("日本語", 1, 3), " 本");
+        assert_eq!(FileController::render_slice("日本語", 0, 3), "日 ");
+        assert_eq!(FileController::render_slice("cafe\u{301}s", 2, 2), "fe\u{301}");
+        assert_eq!(FileController::render_slice("héllo", 9, 3), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn swaps_are_private_utf8() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("tm-swap-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        for (name, file_mode) in [("new.txt", None), ("shared.txt", Some(0o644)), ("locked.txt", Some(0o444))] {
+            let path = dir.join(name);
+            if let Some(file_mode) = file_mode {
+                fs::write(&path, b"caf\xe9\n").unwrap();
+                fs::set_permissions(&path, fs::Permissions::from_mode(file_mode)).unwrap();
+            }
+            let mut file_ctrlr = FileController::open(path, 4).unwrap();
+            if file_ctrlr.count_rows() == 0 {
+                file_ctrlr.insert_row(0, String::new());
+            }
+            file_ctrlr.insert_char(0, 0, '日'); // doesn't fit Latin-1
+            for dirty in 1..=2 {
+                file_ctrlr.update_swap(dirty).unwrap();
+                assert_eq!(mode(&file_ctrlr.swap_path()), 0o600);
+            }
+            let expected = if file_mode.is_some() { "日café\n" } else { "日\n" };
+            assert_eq!(fs::read_to_string(file_ctrlr.swap_path()).unwrap(), expected);
+            file_ctrlr.remove_swap().unwrap();
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
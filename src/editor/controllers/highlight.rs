@@ -0,0 +1,258 @@
+use crate::editor::controllers::file::FileController;
+use crossterm::style::Color;
+use std::cmp;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    Normal,
+    Heading,
+    Emphasis,
+    Strong,
+    InlineCode,
+    CodeBlock,
+    Link,
+    BlockQuote,
+    ListMarker,
+    Html,
+    SearchMatch,
+    Selection
+}
+
+impl Highlight {
+    pub fn colors(&self) -> (Color, Color) { // (foreground, background)
+        match self {
+            Highlight::Normal => (Color::Reset, Color::Reset),
+            Highlight::Heading => (Color::Cyan, Color::Reset),
+            Highlight::Emphasis => (Color::Magenta, Color::Reset),
+            Highlight::Strong => (Color::Yellow, Color::Reset),
+            Highlight::InlineCode | Highlight::CodeBlock => (Color::Green, Color::Reset),
+            Highlight::Link => (Color::Blue, Color::Reset),
+            Highlight::BlockQuote => (Color::DarkGrey, Color::Reset),
+            Highlight::ListMarker => (Color::Red, Color::Reset),
+            Highlight::Html => (Color::DarkCyan, Color::Reset),
+            Highlight::SearchMatch => (Color::Black, Color::Yellow),
+            Highlight::Selection => (Color::White, Color::DarkBlue)
+        }
+    }
+}
+
+/// Multi-line constructs that are still open at the end of a row.
+#[derive(Clone, PartialEq)]
+enum BlockState {
+    Normal,
+    Fence(String),
+    HtmlComment
+}
+
+struct RowSyntax {
+    highlights: Vec<Highlight>,
+    state: BlockState // state after this row
+}
+
+/// Caches markdown highlighting per row, only rehighlighting from the first
+/// row that changed since the last update.
+#[derive(Default)]
+pub struct SyntaxController {
+    rows: Vec<RowSyntax>
+}
+
+impl SyntaxController {
+    pub fn update(&mut self, file_ctrlr: &mut FileController, up_to: usize) {
+        if let Some(changed) = file_ctrlr.take_changed_from() {
+            self.rows.truncate(changed)
+        }
+        let up_to = cmp::min(up_to, file_ctrlr.count_rows());
+        while self.rows.len() < up_to {
+            let state = self.rows.last().map_or(BlockState::Normal, |row| row.state.clone());
+            let row = file_ctrlr.get_editor_row(self.rows.len());
+            self.rows.push(Self::highlight_row(&row, state))
+        }
+    }
+
+    pub fn highlights(&self, row_idx: usize) -> Vec<Highlight> {
+        self.rows
+            .get(row_idx)
+            .map_or_else(Vec::new, |row| row.highlights.clone())
+    }
+
+    fn highlight_row(row: &str, state: BlockState) -> RowSyntax {
+        let g: Vec<&str> = row.graphemes(true).collect();
+        let mut hl = vec![Highlight::Normal; g.len()];
+        let trimmed = row.trim_start();
+        let indent = g.len() - trimmed.graphemes(true).count();
+        match state {
+            BlockState::Fence(fence) => {
+                hl.fill(Highlight::CodeBlock);
+                let closed = trimmed.starts_with(&fence)
+                    && trimmed.trim_start_matches(fence.chars().next().unwrap()).trim().is_empty();
+                return RowSyntax {
+                    highlights: hl,
+                    state: if closed { BlockState::Normal } else { BlockState::Fence(fence) }
+                }
+            }
+            BlockState::HtmlComment => {
+                return match row.find("-->") {
+                    Some(end) => {
+                        let end = FileController::grapheme_count(&row[..end + 3]);
+                        hl[..end].fill(Highlight::Html);
+                        let mut rest = Self::highlight_row(&row[Self::byte_of(&g, end)..], BlockState::Normal);
+                        hl[end..].copy_from_slice(&rest.highlights);
+                        rest.highlights = hl;
+                        rest
+                    }
+                    None => {
+                        hl.fill(Highlight::Html);
+                        RowSyntax { highlights: hl, state: BlockState::HtmlComment }
+                    }
+                }
+            }
+            BlockState::Normal => {}
+        }
+        if indent < 4 {
+            for fence_char in ['`', '~'] {
+                let len = trimmed.chars().take_while(|c| *c == fence_char).count();
+                if len >= 3 {
+                    hl.fill(Highlight::CodeBlock);
+                    return RowSyntax {
+                        highlights: hl,
+                        state: BlockState::Fence(fence_char.to_string().repeat(len))
+                    }
+                }
+            }
+            let level = trimmed.chars().take_while(|c| *c == '#').count();
+            if (1..=6).contains(&level) && trimmed[level..].chars().next().is_none_or(char::is_whitespace) {
+                hl.fill(Highlight::Heading);
+                return RowSyntax { highlights: hl, state: BlockState::Normal }
+            }
+        }
+        let mut i = indent;
+        let mut base = Highlight::Normal;
+        while g.get(i) == Some(&">") {
+            base = Highlight::BlockQuote;
+            hl[i] = Highlight::BlockQuote;
+            i += 1;
+            while g.get(i) == Some(&" ") { i += 1 }
+        }
+        i += Self::list_marker(&g[i..], &mut hl[i..]);
+        hl[i..].fill(base);
+        let state = Self::highlight_inline(&g, i, &mut hl);
+        RowSyntax { highlights: hl, state }
+    }
+
+    fn list_marker(g: &[&str], hl: &mut [Highlight]) -> usize {
+        let digits = g.iter().take_while(|s| s.len() == 1 && s.as_bytes()[0].is_ascii_digit()).count();
+        let len = match g.first() {
+            Some(&"-" | &"*" | &"+") => 1,
+            _ if digits > 0 && matches!(g.get(digits), Some(&"." | &")")) => digits + 1,
+            _ => return 0
+        };
+        if !matches!(g.get(len), Some(&" " | &"\t") | None) {
+            return 0
+        }
+        hl[..len].fill(Highlight::ListMarker);
+        len
+    }
+
+    fn highlight_inline(g: &[&str], start: usize, hl: &mut [Highlight]) -> BlockState {
+        let mut i = start;
+        while i < g.len() {
+            let run = |ch: &str, from: usize| g[from..].iter().take_while(|s| **s == ch).count();
+            match g[i] {
+                "\\" => i += 2,
+                "`" => {
+                    let n = run("`", i);
+                    let close = (i + n..g.len()).find(|&j| {
+                        g[j] == "`" && run("`", j) == n && g[j - 1] != "`"
+                    });
+                    match close {
+                        Some(j) => {
+                            hl[i..j + n].fill(Highlight::InlineCode);
+                            i = j + n
+                        }
+                        None => i += n
+                    }
+                }
+                delim @ ("*" | "_") => {
+                    let n = run(delim, i);
+                    let intraword = delim == "_" && i > 0 && g[i - 1].chars().all(char::is_alphanumeric);
+                    let opens = !intraword && g.get(i + n).is_some_and(|s| !s.trim().is_empty());
+                    let want = cmp::min(n, 2);
+                    let close = if opens {
+                        (i + n..g.len()).find(|&j| {
+                            g[j] == delim && run(delim, j) >= want && !g[j - 1].trim().is_empty()
+                        })
+                    } else {
+                        None
+                    };
+                    match close {
+                        Some(j) => {
+                            let kind = if want == 2 { Highlight::Strong } else { Highlight::Emphasis };
+                            hl[i..j + want].fill(kind);
+                            i = j + want
+                        }
+                        None => i += n
+                    }
+                }
+                "!" if g.get(i + 1) == Some(&"[") => {
+                    i += Self::link(g, i + 1, hl).map_or(1, |len| {
+                        hl[i] = Highlight::Link;
+                        len + 1
+                    })
+                }
+                "[" => i += Self::link(g, i, hl).unwrap_or(1),
+                "<" => {
+                    let rest: String = g[i..].concat();
+                    if rest.starts_with("<!--") {
+                        match rest.find("-->") {
+                            Some(end) => {
+                                let len = FileController::grapheme_count(&rest[..end + 3]);
+                                hl[i..i + len].fill(Highlight::Html);
+                                i += len
+                            }
+                            None => {
+                                hl[i..].fill(Highlight::Html);
+                                return BlockState::HtmlComment
+                            }
+                        }
+                    } else {
+                        match g[i..].iter().position(|s| *s == ">") {
+                            Some(len) if len > 1 => {
+                                let inner = &rest[1..FileController::byte_index(&rest, len)];
+                                let kind = if inner.contains("://") || inner.contains('@') {
+                                    Highlight::Link
+                                } else if inner.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+                                    Highlight::Html
+                                } else {
+                                    Highlight::Normal
+                                };
+                                if kind != Highlight::Normal {
+                                    hl[i..=i + len].fill(kind);
+                                    i += len
+                                }
+                                i += 1
+                            }
+                            _ => i += 1
+                        }
+                    }
+                }
+                _ => i += 1
+            }
+        }
+        BlockState::Normal
+    }
+
+    fn link(g: &[&str], start: usize, hl: &mut [Highlight]) -> Option<usize> { // `[text](url)` at start
+        let text_end = start + g[start..].iter().position(|s| *s == "]")?;
+        if g.get(text_end + 1) != Some(&"(") {
+            return None
+        }
+        let url_end = text_end + 1 + g[text_end + 1..].iter().position(|s| *s == ")")?;
+        hl[start..=url_end].fill(Highlight::Link);
+        Some(url_end + 1 - start)
+    }
+
+    fn byte_of(g: &[&str], idx: usize) -> usize {
+        g[..idx].iter().map(|s| s.len()).sum()
+    }
+}
@@ -0,0 +1,150 @@
+#[derive(Clone, Copy, PartialEq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Other
+}
+
+pub struct Edit {
+    pub row: usize,
+    pub old_rows: Vec<String>,
+    pub new_rows: Vec<String>,
+    pub cursor_before: (usize, usize),
+    pub cursor_after: (usize, usize),
+    pub kind: EditKind
+}
+
+impl Edit {
+    fn can_merge(&self, next: &Edit) -> bool { // consecutive typing/deleting within a single row
+        self.kind != EditKind::Other
+            && self.kind == next.kind
+            && self.row == next.row
+            && self.cursor_after == next.cursor_before
+            && self.new_rows.len() == 1
+            && next.old_rows.len() == 1
+            && next.new_rows.len() == 1
+    }
+}
+
+pub struct HistoryController {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    saved_at: Option<usize>
+}
+
+impl Default for HistoryController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HistoryController {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            saved_at: Some(0)
+        }
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        self.redo_stack.clear();
+        if self.saved_at.is_some_and(|saved| saved > self.undo_stack.len()) {
+            self.saved_at = None // the saved state was undone and can no longer be reached
+        }
+        if !self.is_saved() {
+            if let Some(last) = self.undo_stack.last_mut() {
+                if last.can_merge(&edit) {
+                    last.new_rows = edit.new_rows;
+                    last.cursor_after = edit.cursor_after;
+                    return
+                }
+            }
+        }
+        self.undo_stack.push(edit)
+    }
+
+    pub fn undo(&mut self) -> Option<&Edit> {
+        let edit = self.undo_stack.pop()?;
+        self.redo_stack.push(edit);
+        self.redo_stack.last()
+    }
+
+    pub fn redo(&mut self) -> Option<&Edit> {
+        let edit = self.redo_stack.pop()?;
+        self.undo_stack.push(edit);
+        self.undo_stack.last()
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_at = Some(self.undo_stack.len())
+    }
+
+    pub fn mark_unsaved(&mut self) { // for changes outside the history, like converting line endings
+        self.saved_at = None
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved_at == Some(self.undo_stack.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(row: usize, before: &str, after: &str, x: usize) -> Edit {
+        Edit {
+            row,
+            old_rows: vec![before.into()],
+            new_rows: vec![after.into()],
+            cursor_before: (x, row),
+            cursor_after: (x + 1, row),
+            kind: EditKind::Insert
+        }
+    }
+
+    #[test]
+    fn typing_merges_into_one_step() {
+        let mut history = HistoryController::new();
+        history.record(typed(0, "", "a", 0));
+        assert!(!history.is_saved());
+        history.record(typed(0, "a", "ab", 1));
+        history.record(typed(0, "ab", "abc", 2));
+        history.record(typed(1, "", "x", 0)); // another row starts a new step
+        assert_eq!(history.undo().map(|edit| edit.new_rows.clone()), Some(vec!["x".into()]));
+        let edit = history.undo().unwrap();
+        assert_eq!((edit.old_rows.clone(), edit.new_rows.clone()), (vec!["".into()], vec!["abc".into()]));
+        assert_eq!((edit.cursor_before, edit.cursor_after), ((0, 0), (3, 0)));
+        assert!(history.undo().is_none());
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn typing_after_a_save_starts_a_new_step() {
+        let mut history = HistoryController::new();
+        history.record(typed(0, "", "a", 0));
+        history.mark_saved();
+        history.record(typed(0, "a", "ab", 1));
+        history.undo();
+        assert!(history.is_saved());
+        history.undo();
+        assert!(!history.is_saved());
+        history.redo();
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn undone_save_point_is_gone_after_a_new_edit() {
+        let mut history = HistoryController::new();
+        history.record(typed(0, "", "a", 0));
+        history.mark_saved();
+        history.undo();
+        history.record(typed(0, "", "b", 0)); // replaces the saved edit, which can't be redone anymore
+        assert!(history.redo().is_none());
+        history.undo();
+        assert!(!history.is_saved());
+        history.redo();
+        assert!(!history.is_saved());
+    }
+}
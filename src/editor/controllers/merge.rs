@@ -0,0 +1,120 @@
+const MAX_DIFF_CELLS: usize = 4_000_000; // beyond this the changed middle is treated as one block
+
+const CONFLICT_START: &str = "<<<<<<< yours";
+const CONFLICT_SEPARATOR: &str = "=======";
+const CONFLICT_END: &str = ">>>>>>> on disk";
+
+/// Line based three-way merge of two versions that both started out as `base`.
+/// Changes made on one side only are taken as they are, overlapping ones are
+/// kept side by side between conflict markers. Returns the rows and the number of conflicts.
+pub fn merge(base: &[String], yours: &[String], theirs: &[String]) -> (Vec<String>, usize) {
+    let to_yours = matches(base, yours);
+    let to_theirs = matches(base, theirs);
+    let mut merged = Vec::new();
+    let mut conflicts = 0;
+    let (mut i, mut a, mut b) = (0, 0, 0);
+    loop {
+        let stable = (i..base.len()).find_map(|j| Some((j, to_yours[j]?, to_theirs[j]?)));
+        if stable == Some((i, a, b)) { // unchanged on both sides
+            merged.push(base[i].clone());
+            (i, a, b) = (i + 1, a + 1, b + 1);
+            continue
+        }
+        let (j, next_a, next_b) = stable.unwrap_or((base.len(), yours.len(), theirs.len()));
+        let (old, mine, other) = (&base[i..j], &yours[a..next_a], &theirs[b..next_b]);
+        if mine == old || mine == other {
+            merged.extend_from_slice(other)
+        } else if other == old {
+            merged.extend_from_slice(mine)
+        } else {
+            conflicts += 1;
+            merged.push(CONFLICT_START.into());
+            merged.extend_from_slice(mine);
+            merged.push(CONFLICT_SEPARATOR.into());
+            merged.extend_from_slice(other);
+            merged.push(CONFLICT_END.into());
+        }
+        if stable.is_none() {
+            return (merged, conflicts)
+        }
+        (i, a, b) = (j, next_a, next_b);
+    }
+}
+
+/// For every row of `base`, the row of `other` it was kept as, following a longest common subsequence.
+fn matches(base: &[String], other: &[String]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; base.len()];
+    let prefix = base.iter().zip(other).take_while(|(a, b)| a == b).count();
+    let suffix = base[prefix..].iter().rev().zip(other[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    (0..prefix).for_each(|idx| matched[idx] = Some(idx));
+    (1..=suffix).for_each(|idx| matched[base.len() - idx] = Some(other.len() - idx));
+    let old = &base[prefix..base.len() - suffix];
+    let new = &other[prefix..other.len() - suffix];
+    if old.is_empty() || new.is_empty() || old.len() * new.len() > MAX_DIFF_CELLS {
+        return matched
+    }
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width]; // common subsequence length of old[i..] and new[j..]
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            }
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            matched[prefix + i] = Some(prefix + j);
+            (i, j) = (i + 1, j + 1)
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1
+        } else {
+            j += 1
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    fn merged(base: &str, yours: &str, theirs: &str) -> (String, usize) {
+        let (rows, conflicts) = merge(&rows(base), &rows(yours), &rows(theirs));
+        (rows.join(" "), conflicts)
+    }
+
+    #[test]
+    fn separate_changes_merge_cleanly() {
+        assert_eq!(merged("a b c d e", "a B c d e", "a b c D e"), ("a B c D e".into(), 0));
+        assert_eq!(merged("a b c", "x a b c", "a b c y"), ("x a b c y".into(), 0));
+        assert_eq!(merged("a b c", "a b2 c", "a b2 c"), ("a b2 c".into(), 0)); // the same change on both sides
+        assert_eq!(merged("a b c", "a b c", "a b c"), ("a b c".into(), 0));
+    }
+
+    #[test]
+    fn overlapping_changes_conflict() {
+        let (text, conflicts) = merged("a b c", "a mine c", "a theirs c");
+        assert_eq!(conflicts, 1);
+        assert_eq!(text, format!("a {} mine {} theirs {} c", CONFLICT_START, CONFLICT_SEPARATOR, CONFLICT_END));
+        let (_, conflicts) = merged("a b c d", "a B C d", "a b X d"); // adjacent rows in one changed block
+        assert_eq!(conflicts, 1);
+    }
+
+    #[test]
+    fn deletions_on_both_sides() {
+        assert_eq!(merged("a b c d e", "a c d e", "a b c e"), ("a c e".into(), 0));
+        assert_eq!(merged("a b c", "a c", "a c"), ("a c".into(), 0));
+        assert_eq!(merged("a b c", "", ""), ("".into(), 0));
+        let (text, conflicts) = merged("a b c", "a c", "a B c"); // deleted on one side, changed on the other
+        assert_eq!(conflicts, 1);
+        assert_eq!(text, format!("a {} {} B {} c", CONFLICT_START, CONFLICT_SEPARATOR, CONFLICT_END));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::editor::controllers::position::View;
+use std::cmp;
+
+const MIN_RATIO: usize = 10;
+const MAX_RATIO: usize = 90;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Rect {
+    pub fn contains(&self, (x, y): (usize, usize)) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// A window onto one of the open buffers. The focused pane's view lives
+/// in the `CursorController`, `view` only holds it for the others.
+pub struct Pane {
+    pub buffer: usize,
+    pub view: View,
+    pub area: Rect // text rows plus the status bar below them
+}
+
+enum Layout {
+    Pane(usize),
+    Split {
+        vertical: bool, // side by side, with a separator column between them
+        ratio: usize, // percentage of the space given to `first`
+        first: Box<Layout>,
+        second: Box<Layout>
+    }
+}
+
+impl Layout {
+    fn contains(&self, pane: usize) -> bool {
+        match self {
+            Layout::Pane(idx) => *idx == pane,
+            Layout::Split { first, second, .. } => first.contains(pane) || second.contains(pane)
+        }
+    }
+
+    fn arrange(&self, area: Rect, panes: &mut [Pane], separators: &mut Vec<Rect>) {
+        match self {
+            Layout::Pane(idx) => panes[*idx].area = area,
+            Layout::Split { vertical: true, ratio, first, second } => {
+                let available = area.width.saturating_sub(1);
+                let width = available * ratio / 100;
+                first.arrange(Rect { width, ..area }, panes, separators);
+                separators.push(Rect { x: area.x + width, width: cmp::min(1, area.width), ..area });
+                second.arrange(Rect { x: area.x + width + 1, width: available - width, ..area }, panes, separators);
+            }
+            Layout::Split { vertical: false, ratio, first, second } => {
+                let height = area.height * ratio / 100;
+                first.arrange(Rect { height, ..area }, panes, separators);
+                second.arrange(Rect { y: area.y + height, height: area.height - height, ..area }, panes, separators);
+            }
+        }
+    }
+
+    fn split(&mut self, pane: usize, new_pane: usize, vertical: bool) {
+        match self {
+            Layout::Pane(idx) if *idx == pane => {
+                *self = Layout::Split {
+                    vertical,
+                    ratio: 50,
+                    first: Box::new(Layout::Pane(pane)),
+                    second: Box::new(Layout::Pane(new_pane))
+                }
+            }
+            Layout::Pane(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(pane, new_pane, vertical);
+                second.split(pane, new_pane, vertical)
+            }
+        }
+    }
+
+    fn remove(self, pane: usize) -> Option<Layout> { // the layout without `pane`, renumbering the ones after it
+        match self {
+            Layout::Pane(idx) if idx == pane => None,
+            Layout::Pane(idx) => Some(Layout::Pane(if idx > pane { idx - 1 } else { idx })),
+            Layout::Split { vertical, ratio, first, second } => match (first.remove(pane), second.remove(pane)) {
+                (Some(first), Some(second)) => Some(Layout::Split {
+                    vertical,
+                    ratio,
+                    first: Box::new(first),
+                    second: Box::new(second)
+                }),
+                (Some(rest), None) | (None, Some(rest)) => Some(rest),
+                (None, None) => None
+            }
+        }
+    }
+
+    fn resize(&mut self, pane: usize, delta: isize) -> bool { // grows `pane` in the innermost split holding it
+        let Layout::Split { ratio, first, second, .. } = self else { return false };
+        let in_first = first.contains(pane);
+        if !in_first && !second.contains(pane) {
+            return false
+        }
+        if first.resize(pane, delta) || second.resize(pane, delta) {
+            return true
+        }
+        let delta = if in_first { delta } else { -delta };
+        *ratio = ratio.saturating_add_signed(delta).clamp(MIN_RATIO, MAX_RATIO);
+        true
+    }
+}
+
+pub struct PaneController {
+    pub panes: Vec<Pane>,
+    layout: Layout,
+    pub active: usize,
+    pub separators: Vec<Rect> // columns between side by side panes
+}
+
+impl Default for PaneController {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl PaneController {
+    pub fn new(buffer: usize) -> Self {
+        Self {
+            panes: vec![Pane { buffer, view: View::default(), area: Rect::default() }],
+            layout: Layout::Pane(0),
+            active: 0,
+            separators: Vec::new()
+        }
+    }
+
+    pub fn arrange(&mut self, area: Rect) {
+        self.separators.clear();
+        self.layout.arrange(area, &mut self.panes, &mut self.separators)
+    }
+
+    pub fn split(&mut self, vertical: bool, view: View) {
+        let buffer = self.panes[self.active].buffer;
+        self.panes.push(Pane { buffer, view, area: Rect::default() });
+        self.layout.split(self.active, self.panes.len() - 1, vertical);
+        self.active = self.panes.len() - 1
+    }
+
+    /// Closes the active pane and focuses the one that took its place.
+    pub fn close(&mut self) -> bool {
+        if self.panes.len() == 1 {
+            return false
+        }
+        let layout = std::mem::replace(&mut self.layout, Layout::Pane(0));
+        self.layout = layout.remove(self.active).unwrap_or(Layout::Pane(0));
+        self.panes.remove(self.active);
+        self.active = self.active.saturating_sub(1);
+        true
+    }
+
+    pub fn resize(&mut self, delta: isize) {
+        self.layout.resize(self.active, delta);
+    }
+
+    pub fn next(&self, forward: bool) -> usize {
+        let count = self.panes.len();
+        if forward { (self.active + 1) % count } else { (self.active + count - 1) % count }
+    }
+
+    pub fn pane_at(&self, point: (usize, usize)) -> Option<usize> {
+        self.panes.iter().position(|pane| pane.area.contains(point))
+    }
+}
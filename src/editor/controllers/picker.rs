@@ -0,0 +1,124 @@
+use crossterm::event::KeyCode;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub const PICKER_HEIGHT: usize = 10;
+
+/// A filterable list shown over the bottom of the text area while a prompt is open.
+pub struct Picker {
+    items: Vec<(String, String)>, // (label, detail shown on the right)
+    bonus: Vec<i64>, // added to the fuzzy score of the item with the same index
+    filtered: Vec<usize>,
+    selected: usize,
+    moved: bool, // the selection was picked with the arrow keys rather than by filtering
+    pub preview: Option<Vec<String>> // shown above the list in place of the text
+}
+
+impl Picker {
+    pub fn new(items: Vec<(String, String)>) -> Self {
+        let filtered = (0..items.len()).collect();
+        Self {
+            bonus: vec![0; items.len()],
+            items,
+            filtered,
+            selected: 0,
+            moved: false,
+            preview: None
+        }
+    }
+
+    pub fn with_bonus(mut self, bonus: Vec<i64>) -> Self {
+        self.bonus = bonus;
+        self
+    }
+
+    pub fn update(&mut self, query: &str, key: KeyCode) {
+        match key {
+            KeyCode::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                self.moved = true
+            }
+            KeyCode::Down => {
+                if self.selected + 1 < self.filtered.len() {
+                    self.selected += 1
+                }
+                self.moved = true
+            }
+            KeyCode::Enter | KeyCode::Esc => {}
+            _ => self.filter(query)
+        }
+    }
+
+    fn filter(&mut self, query: &str) {
+        let mut scored: Vec<(i64, usize)> = self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (label, _))| fuzzy_score(query, label).map(|score| (score + self.bonus[idx], idx)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.filtered = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = 0;
+        self.moved = false
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.filtered.get(self.selected).copied()
+    }
+
+    pub fn selected_label(&self) -> Option<&str> {
+        self.selected().map(|idx| self.items[idx].0.as_str())
+    }
+
+    pub fn moved(&self) -> bool {
+        self.moved
+    }
+
+    pub fn lines(&self, width: usize) -> Vec<(String, bool)> { // (line, is selected)
+        let first = self.selected.saturating_sub(PICKER_HEIGHT - 1);
+        self.filtered
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(PICKER_HEIGHT)
+            .map(|(pos, idx)| {
+                let (label, detail) = &self.items[*idx];
+                let label_width = width.saturating_sub(detail.width() + 1);
+                let mut shown = String::new();
+                for ch in label.chars() {
+                    if shown.width() + ch.width().unwrap_or(0) > label_width {
+                        break
+                    }
+                    shown.push(ch)
+                }
+                let padding = width.saturating_sub(shown.width() + detail.width());
+                (format!("{}{}{}", shown, " ".repeat(padding), detail), pos == self.selected)
+            })
+            .collect()
+    }
+}
+
+/// Scores `candidate` if it contains every character of `query` in order,
+/// preferring consecutive runs and matches at the start of words.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut chars = candidate.char_indices();
+    let mut prev: Option<(usize, char)> = None;
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_lowercase().next().unwrap_or(q);
+        let (idx, ch) = loop {
+            let (idx, ch) = chars.next()?;
+            if ch.to_lowercase().next() == Some(q) {
+                break (idx, ch)
+            }
+        };
+        let before = candidate[..idx].chars().next_back();
+        score += 1;
+        if prev.is_some_and(|(prev_idx, prev_ch)| prev_idx + prev_ch.len_utf8() == idx) {
+            score += 5
+        }
+        if before.is_none_or(|c| !c.is_alphanumeric()) || (ch.is_uppercase() && before.is_some_and(char::is_lowercase)) {
+            score += 3
+        }
+        prev = Some((idx, ch));
+    }
+    Some(score * 100 - candidate.len() as i64)
+}
@@ -1,17 +1,44 @@
-use crate::editor::controllers::file::{Row, FileController};
+use crate::editor::controllers::file::FileController;
 use std::cmp;
 use crossterm::event::*;
+use unicode_width::UnicodeWidthStr;
 
-const TAB_STOP: usize = 4;
+#[derive(Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Soft, // long rows are wrapped on screen only
+    Hard  // typing past the edge of the screen breaks the row in the file
+}
+
+/// Where the cursor and the scroll offsets were, to come back to later.
+#[derive(Clone, Copy, Default)]
+pub struct View {
+    pub cursor: (usize, usize),
+    pub anchor: Option<(usize, usize)>,
+    pub row_offset: usize,
+    pub segment_offset: usize,
+    pub column_offset: usize,
+    scrolled_at: Option<(usize, usize)>
+}
 
 pub struct CursorController {
     pub cursor_x: usize,
     pub cursor_y: usize,
+    pub anchor: Option<(usize, usize)>, // other end of the selection, (x, y) like the cursor
     pub editor_height: usize,
-    pub editor_width: usize,
+    pub editor_width: usize, // width of the text area
+    pub left_margin: usize,
+    pub text_width: Option<usize>, // None uses the whole terminal
+    pub wrap_column: Option<usize>, // None wraps at the edge of the text area
+    pub centered: bool,
+    screen_width: usize,
     pub row_offset: usize,
+    pub segment_offset: usize, // first visible wrapped segment of the row at row_offset
     pub column_offset: usize,
-    render_x: usize
+    pub wrap_mode: WrapMode,
+    render_x: usize,
+    screen_y: usize,
+    visible: bool, // false while the view is scrolled away from the cursor
+    scrolled_at: Option<(usize, usize)> // cursor when the view was last scrolled without moving it
 }
 
 impl Default for CursorController {
@@ -25,64 +52,358 @@ impl CursorController {
         Self {
             cursor_x: 0,
             cursor_y: 0,
+            anchor: None,
             editor_height: win_size.1,
-            editor_width: if win_size.0 > 100 { // if editor is too wide, set to 100 cols
-                100
-            } else { 
-                win_size.0 
-            },
+            editor_width: win_size.0,
+            left_margin: 0,
+            text_width: None,
+            wrap_column: None,
+            centered: false,
+            screen_width: win_size.0,
             row_offset: 0,
+            segment_offset: 0,
             column_offset: 0,
-            render_x: 0
+            wrap_mode: WrapMode::Soft,
+            render_x: 0,
+            screen_y: 0,
+            visible: true,
+            scrolled_at: None
+        }
+    }
+
+    pub fn pos(&self) -> Option<(usize, usize)> { // on screen
+        self.visible.then(|| (self.left_margin + self.render_x - self.column_offset, self.screen_y))
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            cursor: (self.cursor_x, self.cursor_y),
+            anchor: self.anchor,
+            row_offset: self.row_offset,
+            segment_offset: self.segment_offset,
+            column_offset: self.column_offset,
+            scrolled_at: self.scrolled_at
+        }
+    }
+
+    pub fn set_view(&mut self, view: View) {
+        (self.cursor_x, self.cursor_y) = view.cursor;
+        self.anchor = view.anchor;
+        self.row_offset = view.row_offset;
+        self.segment_offset = view.segment_offset;
+        self.column_offset = view.column_offset;
+        self.scrolled_at = view.scrolled_at
+    }
+
+    pub fn clamp(&mut self, editor_rows: &FileController) { // after the rows changed behind this view's back
+        let rows = editor_rows.count_rows();
+        let clamp = |(x, y): (usize, usize)| {
+            let y = cmp::min(y, rows);
+            (if y < rows { cmp::min(x, editor_rows.row_len(y)) } else { 0 }, y)
+        };
+        (self.cursor_x, self.cursor_y) = clamp((self.cursor_x, self.cursor_y));
+        self.anchor = self.anchor.map(clamp);
+        self.row_offset = cmp::min(self.row_offset, rows);
+    }
+
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> { // (start, end), ordered
+        let anchor = self.anchor?;
+        let cursor = (self.cursor_x, self.cursor_y);
+        match (anchor.1, anchor.0).cmp(&(cursor.1, cursor.0)) {
+            cmp::Ordering::Less => Some((anchor, cursor)),
+            cmp::Ordering::Greater => Some((cursor, anchor)),
+            cmp::Ordering::Equal => None
+        }
+    }
+
+    pub fn resize(&mut self, win_size: (usize, usize)) {
+        self.screen_width = win_size.0;
+        self.editor_height = win_size.1;
+        self.update_layout();
+    }
+
+    pub fn update_layout(&mut self) {
+        self.editor_width = self.text_width.map_or(self.screen_width, |width| {
+            cmp::min(width, self.screen_width)
+        });
+        self.left_margin = if self.centered {
+            (self.screen_width - self.editor_width) / 2
+        } else {
+            0
+        };
+    }
+
+    pub fn wrap_width(&self) -> usize {
+        self.wrap_column.map_or(self.editor_width, |column| cmp::min(column, self.editor_width))
+    }
+
+    /// Splits rendered cells into screen lines no wider than `width`, breaking
+    /// after whitespace where possible. Returns the first cell of every line.
+    pub fn wrap_segments(cells: &[String], width: usize) -> Vec<usize> {
+        let mut starts = vec![0];
+        let mut col = 0;
+        let mut last_break = None;
+        for (idx, cell) in cells.iter().enumerate() {
+            let cell_width = cell.width();
+            if col + cell_width > width && idx > *starts.last().unwrap() {
+                let start = last_break.unwrap_or(idx);
+                starts.push(start);
+                col = cells[start..idx].iter().map(|it| it.width()).sum();
+                last_break = None;
+            }
+            col += cell_width;
+            if cell.trim().is_empty() {
+                last_break = Some(idx + 1)
+            }
+        }
+        starts
+    }
+
+    pub fn row_segments(&self, editor_rows: &FileController, row_idx: usize) -> Vec<usize> {
+        if row_idx >= editor_rows.count_rows() {
+            return vec![0]
+        }
+        Self::wrap_segments(&editor_rows.get_render(row_idx), self.wrap_width())
+    }
+
+    fn segment_of(segments: &[usize], x: usize) -> usize {
+        segments.iter().rposition(|start| *start <= x).unwrap_or(0)
+    }
+
+    fn segment_x(cells: &[String], start: usize, x: usize) -> usize {
+        cells[start..cmp::min(x, cells.len())].iter().map(|it| it.width()).sum()
+    }
+
+    fn prev_segment(&self, editor_rows: &FileController, (row, segment): (usize, usize)) -> Option<(usize, usize)> {
+        if segment > 0 {
+            Some((row, segment - 1))
+        } else if row > 0 {
+            Some((row - 1, self.row_segments(editor_rows, row - 1).len() - 1))
+        } else {
+            None
+        }
+    }
+
+    fn next_segment(&self, editor_rows: &FileController, (row, segment): (usize, usize)) -> Option<(usize, usize)> {
+        if segment + 1 < self.row_segments(editor_rows, row).len() {
+            Some((row, segment + 1))
+        } else if row + 1 < editor_rows.count_rows() {
+            Some((row + 1, 0))
+        } else {
+            None
         }
     }
 
-    pub fn pos(&self) -> (usize, usize) {
-        (self.render_x, self.cursor_y)
+    fn x_at_column(cells: &[String], segments: &[usize], segment: usize, col: usize) -> usize {
+        let end = segments.get(segment + 1).map_or(cells.len(), |next| next - 1);
+        let mut x = segments[segment];
+        let mut width = 0;
+        while x < end && width + cells[x].width() <= col {
+            width += cells[x].width();
+            x += 1
+        }
+        x
+    }
+
+    /// Maps a point on the screen to the (x, y) cursor position showing there.
+    pub fn position_at(&self, editor_rows: &FileController, (col, line): (usize, usize)) -> (usize, usize) {
+        let col = col.saturating_sub(self.left_margin);
+        let rows = editor_rows.count_rows();
+        let (row, segment) = match self.wrap_mode {
+            WrapMode::Soft => {
+                let mut at = (self.row_offset, self.segment_offset);
+                for _ in 0..line {
+                    match self.next_segment(editor_rows, at) {
+                        Some(next) => at = next,
+                        None => break
+                    }
+                }
+                at
+            }
+            WrapMode::Hard => (self.row_offset + line, 0)
+        };
+        if row >= rows {
+            return if rows == 0 { (0, 0) } else { (editor_rows.row_len(rows - 1), rows - 1) }
+        }
+        let cells = editor_rows.get_render(row);
+        let x = match self.wrap_mode {
+            WrapMode::Soft => Self::x_at_column(&cells, &Self::wrap_segments(&cells, self.wrap_width()), segment, col),
+            WrapMode::Hard => Self::x_at_column(&cells, &[0], 0, col + self.column_offset)
+        };
+        (x, row)
+    }
+
+    /// Moves the view by `lines` screen lines, leaving the cursor where it is.
+    pub fn scroll_view(&mut self, editor_rows: &FileController, lines: isize) {
+        self.scrolled_at = Some((self.cursor_x, self.cursor_y));
+        if self.wrap_mode == WrapMode::Hard {
+            let last = editor_rows.count_rows().saturating_sub(1);
+            self.row_offset = cmp::min(self.row_offset.saturating_add_signed(lines), last);
+            return
+        }
+        for _ in 0..lines.unsigned_abs() {
+            let top = (self.row_offset, self.segment_offset);
+            let next = if lines < 0 {
+                self.prev_segment(editor_rows, top)
+            } else {
+                self.next_segment(editor_rows, top)
+            };
+            match next {
+                Some(next) => (self.row_offset, self.segment_offset) = next,
+                None => break
+            }
+        }
     }
 
-    pub fn get_render_x(&self, row: &Row) -> usize {
-        row.content[..self.cursor_x]
-            .chars()
-            .fold(0, |render_x, c| {
-                if c == '\t' {
-                    render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
-                } else {
-                    render_x + 1
+    fn place_in_view(&mut self, editor_rows: &FileController) { // finds the cursor without scrolling to it
+        let cells = if self.cursor_y < editor_rows.count_rows() {
+            editor_rows.get_render(self.cursor_y)
+        } else {
+            Vec::new()
+        };
+        if self.wrap_mode == WrapMode::Hard {
+            self.render_x = self.get_render_x(&cells);
+            self.visible = (self.row_offset..self.row_offset + self.editor_height).contains(&self.cursor_y)
+                && (self.column_offset..self.column_offset + self.editor_width).contains(&self.render_x);
+            self.screen_y = self.cursor_y.saturating_sub(self.row_offset);
+            return
+        }
+        let segments = Self::wrap_segments(&cells, self.wrap_width());
+        let cursor = (self.cursor_y, Self::segment_of(&segments, self.cursor_x));
+        self.render_x = cmp::min(
+            Self::segment_x(&cells, segments[cursor.1], self.cursor_x),
+            self.editor_width.saturating_sub(1)
+        );
+        let mut at = (self.row_offset, self.segment_offset);
+        self.visible = false;
+        for screen_y in 0..self.editor_height {
+            if at == cursor {
+                self.visible = true;
+                self.screen_y = screen_y;
+                break
+            }
+            match self.next_segment(editor_rows, at) {
+                Some(next) => at = next,
+                None if cursor == (at.0 + 1, 0) && screen_y + 1 < self.editor_height => { // past the last row
+                    self.visible = true;
+                    self.screen_y = screen_y + 1;
+                    break
                 }
-            })
+                None => break
+            }
+        }
+    }
+
+    fn move_vertically(&mut self, editor_rows: &FileController, up: bool) { // by one screen line
+        let cells = if self.cursor_y < editor_rows.count_rows() {
+            editor_rows.get_render(self.cursor_y)
+        } else {
+            Vec::new()
+        };
+        let segments = Self::wrap_segments(&cells, self.wrap_width());
+        let segment = Self::segment_of(&segments, self.cursor_x);
+        let col = Self::segment_x(&cells, segments[segment], self.cursor_x);
+        let target = if up {
+            self.prev_segment(editor_rows, (self.cursor_y, segment))
+        } else if segment + 1 < segments.len() {
+            Some((self.cursor_y, segment + 1))
+        } else if self.cursor_y < editor_rows.count_rows() {
+            Some((self.cursor_y + 1, 0))
+        } else {
+            None
+        };
+        let Some((row, segment)) = target else { return };
+        let cells = if row < editor_rows.count_rows() { editor_rows.get_render(row) } else { Vec::new() };
+        let segments = Self::wrap_segments(&cells, self.wrap_width());
+        self.cursor_y = row;
+        self.cursor_x = Self::x_at_column(&cells, &segments, segment, col);
+    }
+
+    pub fn get_render_x(&self, cells: &[String]) -> usize {
+        cells.iter()
+            .take(self.cursor_x)
+            .map(|cell| cell.width())
+            .sum()
     }
 
     pub fn scroll(&mut self, editor_rows: &FileController) {
+        if self.wrap_mode == WrapMode::Soft && self.row_offset < editor_rows.count_rows() {
+            // the row may wrap into fewer lines since the width changed
+            let last_segment = self.row_segments(editor_rows, self.row_offset).len() - 1;
+            self.segment_offset = cmp::min(self.segment_offset, last_segment);
+        }
+        if self.scrolled_at == Some((self.cursor_x, self.cursor_y)) {
+            return self.place_in_view(editor_rows)
+        }
+        self.scrolled_at = None;
+        self.visible = true;
+        if self.wrap_mode == WrapMode::Soft {
+            return self.scroll_wrapped(editor_rows)
+        }
+        self.segment_offset = 0;
         self.render_x = 0;
         if self.cursor_y < editor_rows.count_rows() {
-            self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y))
+            self.render_x = self.get_render_x(&editor_rows.get_render(self.cursor_y))
         }
         self.row_offset = cmp::min(self.row_offset, self.cursor_y);
         if self.cursor_y >= self.row_offset + self.editor_height {
-            self.row_offset = self.cursor_y - self.editor_height + 1
+            self.row_offset = self.cursor_y + 1 - cmp::max(self.editor_height, 1)
         }
-        self.column_offset = cmp::min(self.column_offset, self.cursor_x);
-        if self.cursor_x >= self.column_offset + self.editor_width {
-            self.column_offset = self.cursor_x - self.editor_width + 1
+        self.column_offset = cmp::min(self.column_offset, self.render_x);
+        if self.render_x >= self.column_offset + self.editor_width {
+            self.column_offset = self.render_x + 1 - cmp::max(self.editor_width, 1)
         }
+        self.screen_y = self.cursor_y - self.row_offset;
+    }
+
+    fn scroll_wrapped(&mut self, editor_rows: &FileController) {
+        let cells = if self.cursor_y < editor_rows.count_rows() {
+            editor_rows.get_render(self.cursor_y)
+        } else {
+            Vec::new()
+        };
+        let segments = Self::wrap_segments(&cells, self.wrap_width());
+        let segment = Self::segment_of(&segments, self.cursor_x);
+        self.column_offset = 0;
+        self.render_x = cmp::min(
+            Self::segment_x(&cells, segments[segment], self.cursor_x),
+            self.editor_width.saturating_sub(1)
+        );
+        let cursor = (self.cursor_y, segment);
+        if cursor < (self.row_offset, self.segment_offset) {
+            (self.row_offset, self.segment_offset) = cursor;
+        }
+        let mut top = cursor; // the highest screen line that still keeps the cursor visible
+        let mut screen_y = 0;
+        while screen_y + 1 < self.editor_height && top > (self.row_offset, self.segment_offset) {
+            match self.prev_segment(editor_rows, top) {
+                Some(prev) => top = prev,
+                None => break
+            }
+            screen_y += 1;
+        }
+        (self.row_offset, self.segment_offset) = top;
+        self.screen_y = screen_y;
     }
 
     pub fn move_cursor(&mut self, direction: KeyCode, editor_rows: &FileController) {
         match direction {
+            KeyCode::Up | KeyCode::Down if self.wrap_mode == WrapMode::Soft => {
+                self.move_vertically(editor_rows, direction == KeyCode::Up)
+            }
             KeyCode::Up => if self.cursor_y != 0 { self.cursor_y -= 1 },
             KeyCode::Left => {
                 if self.cursor_x != 0 { 
                     self.cursor_x -= 1 
                 } else if self.cursor_y > 0 {
                     self.cursor_y -= 1;
-                    self.cursor_x = editor_rows.get_editor_row(self.cursor_y).len();
+                    self.cursor_x = editor_rows.row_len(self.cursor_y);
                 }
             }
             KeyCode::Down => if self.cursor_y < editor_rows.count_rows() { self.cursor_y += 1 },
             KeyCode::Right => {
                 if self.cursor_y < editor_rows.count_rows() {
-                    if self.cursor_x < editor_rows.get_editor_row(self.cursor_y).len() {
+                    if self.cursor_x < editor_rows.row_len(self.cursor_y) {
                         self.cursor_x += 1
                     } else {
                         self.cursor_y += 1;
@@ -92,7 +413,7 @@ impl CursorController {
             }
             KeyCode::End => {
                 if self.cursor_y < editor_rows.count_rows() {
-                    self.cursor_x = editor_rows.get_editor_row(self.cursor_y).len();
+                    self.cursor_x = editor_rows.row_len(self.cursor_y);
                 }
             }
             KeyCode::Home => {
@@ -101,10 +422,41 @@ impl CursorController {
             _ => unimplemented!(),
         }
         let row_len = if self.cursor_y < editor_rows.count_rows() {
-            editor_rows.get_editor_row(self.cursor_y).len()
+            editor_rows.row_len(self.cursor_y)
         } else {
             0
         };
         self.cursor_x = cmp::min(self.cursor_x, row_len);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrolled(text: &str, wrap_mode: WrapMode, cursor: (usize, usize)) -> CursorController {
+        let file_ctrlr = FileController::from_bytes(text.as_bytes(), 4);
+        let mut cursor_ctrlr = CursorController::new((10, 3));
+        cursor_ctrlr.wrap_mode = wrap_mode;
+        (cursor_ctrlr.cursor_x, cursor_ctrlr.cursor_y) = cursor;
+        cursor_ctrlr.scroll(&file_ctrlr);
+        cursor_ctrlr
+    }
+
+    #[test]
+    fn scroll_by_display_columns() {
+        let cursor_ctrlr = scrolled("日本語日本語日本語\n", WrapMode::Hard, (6, 0));
+        assert_eq!((cursor_ctrlr.render_x, cursor_ctrlr.column_offset), (12, 3));
+        let cursor_ctrlr = scrolled("e\u{301}e\u{301}e\u{301}\u{1f468}\u{200d}\u{1f467}\tx\n", WrapMode::Hard, (4, 0));
+        assert_eq!((cursor_ctrlr.render_x, cursor_ctrlr.column_offset), (5, 0));
+        let cursor_ctrlr = scrolled("a\nb\nc\nd\né\n", WrapMode::Hard, (0, 4));
+        assert_eq!((cursor_ctrlr.row_offset, cursor_ctrlr.screen_y), (2, 2));
+    }
+
+    #[test]
+    fn scroll_wrapped_cjk() {
+        let cursor_ctrlr = scrolled("日本語日本語日本語\n", WrapMode::Soft, (7, 0));
+        assert_eq!((cursor_ctrlr.segment_offset, cursor_ctrlr.screen_y, cursor_ctrlr.render_x), (0, 1, 4));
+        let cursor_ctrlr = scrolled("日本語日本語日本語\n", WrapMode::Soft, (9, 0));
+        assert_eq!((cursor_ctrlr.segment_offset, cursor_ctrlr.screen_y), (0, 1));
+    }
+}
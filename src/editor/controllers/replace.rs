@@ -0,0 +1,84 @@
+use crate::editor::controllers::file::FileController;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+pub struct ReplaceController {
+    regex: Regex,
+    template: String,
+    position: (usize, usize), // (byte index, row) where the next match is looked for
+    end: Option<(usize, usize)>, // (byte index, row) matches must end before, when limited to a selection
+    pub current: Option<(usize, usize, usize)>, // (row, start, end) in bytes
+    pub originals: BTreeMap<usize, String>, // rows as they were before their first replacement
+    pub cursor_before: (usize, usize),
+    pub replaced: usize
+}
+
+impl ReplaceController {
+    pub fn new(
+        regex: Regex,
+        template: String,
+        cursor_before: (usize, usize),
+        bounds: Option<((usize, usize), (usize, usize))>
+    ) -> Self {
+        Self {
+            regex,
+            template,
+            position: bounds.map_or((0, 0), |(start, _)| start),
+            end: bounds.map(|(_, end)| end),
+            current: None,
+            originals: BTreeMap::new(),
+            cursor_before,
+            replaced: 0
+        }
+    }
+
+    pub fn next_match(&mut self, file_ctrlr: &FileController) -> Option<(usize, usize, usize)> {
+        let (mut byte_idx, mut row_idx) = self.position;
+        self.current = None;
+        while row_idx < file_ctrlr.count_rows() && self.end.is_none_or(|(_, end_row)| row_idx <= end_row) {
+            let row = file_ctrlr.get_editor_row(row_idx);
+            if byte_idx <= row.len() {
+                if let Some(found) = self.regex
+                    .find_at(&row, byte_idx)
+                    .filter(|found| self.end.is_none_or(|(end, end_row)| row_idx < end_row || found.end() <= end)) {
+                    self.current = Some((row_idx, found.start(), found.end()));
+                    self.position = (Self::after(&row, found.start(), found.end()), row_idx);
+                    return self.current
+                }
+            }
+            row_idx += 1;
+            byte_idx = 0;
+        }
+        self.position = (0, row_idx);
+        None
+    }
+
+    /// Replaces the current match and returns the row with the replacement applied.
+    pub fn replace_current(&mut self, file_ctrlr: &FileController) -> Option<(usize, String)> {
+        let (row_idx, start, end) = self.current.take()?;
+        let row = file_ctrlr.get_editor_row(row_idx);
+        let mut replacement = String::new();
+        if let Some(captures) = self.regex.captures_at(&row, start) {
+            captures.expand(&self.template, &mut replacement);
+        }
+        let new_row = format!("{}{}{}", &row[..start], replacement, &row[end..]);
+        self.originals.entry(row_idx).or_insert(row);
+        if let Some((end_byte, end_row)) = self.end.as_mut() {
+            if *end_row == row_idx {
+                *end_byte = *end_byte + replacement.len() - (end - start);
+            }
+        }
+        let resume = start + replacement.len();
+        self.position = (if end > start { resume } else { Self::after(&new_row, resume, resume) }, row_idx);
+        self.replaced += 1;
+        Some((row_idx, new_row))
+    }
+
+    fn after(row: &str, start: usize, end: usize) -> usize { // skip past empty matches
+        if end > start {
+            end
+        } else {
+            end + row[end..].chars().next().map_or(1, char::len_utf8)
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::editor::controllers::file::FileController;
+use crate::editor::controllers::highlight::Highlight;
+use crate::editor::controllers::position::View;
+
+#[derive(Default)]
+pub struct SearchController {
+    pub query: Option<String>,
+    pub last_match: Option<(usize, usize)>,
+    pub saved_view: Option<View>,
+    pub wrapped: bool
+}
+
+impl SearchController {
+    pub fn start(&mut self, view: View) {
+        self.query = None;
+        self.last_match = None;
+        self.saved_view = Some(view);
+        self.wrapped = false
+    }
+
+    pub fn finish(&mut self) -> Option<View> {
+        self.query = None;
+        self.last_match = None;
+        self.wrapped = false;
+        self.saved_view.take()
+    }
+
+    pub fn find_matches(row: &str, query: &str) -> Vec<(usize, usize)> { // (start, len) in graphemes
+        if query.is_empty() {
+            return Vec::new()
+        }
+        let query_len = FileController::grapheme_count(query);
+        let mut matches = Vec::new();
+        let mut graphemes_before = 0;
+        let mut last_byte = 0;
+        for (byte_idx, _) in row.match_indices(query) {
+            graphemes_before += FileController::grapheme_count(&row[last_byte..byte_idx]);
+            last_byte = byte_idx;
+            matches.push((graphemes_before, query_len));
+        }
+        matches
+    }
+
+    pub fn highlight_matches(&self, row: &str, highlights: &mut [Highlight]) {
+        if let Some(query) = &self.query {
+            for (start, len) in Self::find_matches(row, query) {
+                let end = (start + len).min(highlights.len());
+                highlights[start..end].fill(Highlight::SearchMatch)
+            }
+        }
+    }
+
+    /// Finds the nearest match from `from`, wrapping around the document. A forward
+    /// search accepts a match starting at `from`, a backward one only matches before it.
+    pub fn search(&mut self, file_ctrlr: &FileController, from: (usize, usize), forward: bool) -> Option<(usize, usize)> {
+        let query = self.query.as_ref()?;
+        let rows = file_ctrlr.count_rows();
+        if rows == 0 {
+            return None
+        }
+        let (x, y) = (from.0, from.1.min(rows - 1));
+        for i in 0..=rows {
+            let row_idx = if forward { (y + i) % rows } else { (y + rows - i % rows) % rows };
+            let matches = Self::find_matches(&file_ctrlr.get_editor_row(row_idx), query);
+            let found = if forward {
+                matches.iter().find(|(start, _)| i > 0 || *start >= x)
+            } else {
+                matches.iter().rev().find(|(start, _)| i > 0 || *start < x)
+            };
+            if let Some((start, _)) = found {
+                self.wrapped = if forward { y + i >= rows } else { i > y };
+                self.last_match = Some((*start, row_idx));
+                return self.last_match
+            }
+        }
+        None
+    }
+}
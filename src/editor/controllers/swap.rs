@@ -0,0 +1,59 @@
+use std::{cmp, fs, time};
+use std::path::{Path, PathBuf};
+
+const SWAP_EXTENSION: &str = "tm-swp";
+const UNNAMED_SWAP: &str = ".tm-unnamed.tm-swp";
+
+/// What we know about a document's swap file.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SwapState {
+    Clean, // no swap file of ours
+    Written(u64), // holds the text as of this edit count
+    Failed(u64), // writing failed at this edit count, retried after the next edit
+    Found, // left behind by an earlier session, recovery not offered yet
+    Deferred // left behind by an earlier session and kept, so never overwritten
+}
+
+impl SwapState {
+    pub fn path(filename: Option<&Path>) -> PathBuf { // a hidden file next to the document
+        let Some(filename) = filename else { return PathBuf::from(UNNAMED_SWAP) };
+        let name = filename.file_name().map_or_else(Default::default, |name| name.to_string_lossy());
+        filename.with_file_name(format!(".{}.{}", name, SWAP_EXTENSION))
+    }
+
+    pub fn detect(filename: Option<&Path>) -> Self {
+        if Self::path(filename).is_file() { SwapState::Found } else { SwapState::Clean }
+    }
+
+    /// A short description of how the swap differs from the text, for the recovery prompt.
+    pub fn summary(swap: &Path, current: &[String], swapped: &[String]) -> String {
+        let age = fs::metadata(swap)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map_or_else(String::new, |elapsed| format!("written {} ago, ", Self::describe_age(elapsed)));
+        let prefix = current.iter().zip(swapped).take_while(|(a, b)| a == b).count();
+        let max_suffix = current.len().min(swapped.len()) - prefix;
+        let suffix = current.iter().rev().zip(swapped.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+        if prefix == current.len() && prefix == swapped.len() {
+            return format!("{}same text as the file", age)
+        }
+        format!(
+            "{}lines {}-{} differ: {} in the file, {} in the swap",
+            age,
+            prefix + 1,
+            cmp::max(current.len(), swapped.len()) - suffix,
+            current.len() - prefix - suffix,
+            swapped.len() - prefix - suffix
+        )
+    }
+
+    fn describe_age(elapsed: time::Duration) -> String {
+        match elapsed.as_secs() {
+            secs @ 0..=59 => format!("{}s", secs),
+            secs @ 60..=3599 => format!("{} min", secs / 60),
+            secs @ 3600..=86399 => format!("{} h", secs / 3600),
+            secs => format!("{} days", secs / 86400)
+        }
+    }
+}
@@ -1,22 +1,26 @@
+use crate::editor::controllers::config::Config;
 use std::time;
 use std::io::{self, stdout};
 
 pub struct StatusMessage {
     message: Option<String>,
-    set_time: Option<time::Instant>
+    set_time: Option<time::Instant>,
+    timeout: time::Duration
 }
 
 impl Default for StatusMessage {
     fn default() -> Self {
-        Self::new("Help: Ctrl+S = Save / Ctrl+C = Quit".into())
+        let config = Config::default();
+        Self::new(config.help_text, config.message_timeout)
     }
 }
 
 impl StatusMessage {
-    pub fn new(initial_message: String) -> Self {
+    pub fn new(initial_message: String, timeout: time::Duration) -> Self {
         Self {
             message: Some(initial_message),
             set_time: Some(time::Instant::now()),
+            timeout
         }
     }
 
@@ -27,7 +31,7 @@ impl StatusMessage {
 
     pub fn message(&mut self) -> Option<&String> {
         self.set_time.and_then(|time| {
-            if time.elapsed() > time::Duration::from_secs(5) {
+            if time.elapsed() > self.timeout {
                 self.message = None;
                 self.set_time = None;
                 None
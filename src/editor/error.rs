@@ -0,0 +1,65 @@
+use std::{error, fmt, io};
+use std::path::{Path, PathBuf};
+
+pub type Result<T> = std::result::Result<T, EditorError>;
+
+/// Only `Terminal` errors end the session, file errors are shown in the
+/// message bar and the buffer stays open so nothing is lost.
+#[derive(Debug)]
+pub enum EditorError {
+    Terminal(io::Error), // reading keys or drawing failed, there's no way to go on
+    File {
+        action: &'static str, // what we were doing, e.g. "save"
+        path: Option<PathBuf>,
+        source: io::Error
+    }
+}
+
+impl EditorError {
+    pub fn file(action: &'static str, path: Option<&Path>, source: io::Error) -> Self {
+        EditorError::File { action, path: path.map(Path::to_path_buf), source }
+    }
+
+    fn reason(action: &str, err: &io::Error) -> String { // in words rather than "os error 13"
+        match err.kind() {
+            _ if err.raw_os_error().is_none() => err.to_string(), // already worded by us or a library
+            io::ErrorKind::PermissionDenied => "permission denied".into(),
+            io::ErrorKind::NotFound if action == "save" => "the directory doesn't exist".into(),
+            io::ErrorKind::NotFound => "no such file".into(),
+            io::ErrorKind::StorageFull => "the disk is full".into(),
+            io::ErrorKind::QuotaExceeded => "the disk quota is exceeded".into(),
+            io::ErrorKind::ReadOnlyFilesystem => "the file system is read-only".into(),
+            io::ErrorKind::IsADirectory => "it's a directory".into(),
+            io::ErrorKind::NotADirectory => "part of the path isn't a directory".into(),
+            _ => err.to_string()
+        }
+    }
+}
+
+impl fmt::Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditorError::Terminal(err) => write!(f, "Terminal error: {}", err),
+            EditorError::File { action, path: Some(path), source } => {
+                write!(f, "Couldn't {} {}: {}", action, path.display(), Self::reason(action, source))
+            }
+            EditorError::File { action, path: None, source } => {
+                write!(f, "Couldn't {}: {}", action, Self::reason(action, source))
+            }
+        }
+    }
+}
+
+impl error::Error for EditorError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            EditorError::Terminal(err) | EditorError::File { source: err, .. } => Some(err)
+        }
+    }
+}
+
+impl From<io::Error> for EditorError { // `?` on terminal calls
+    fn from(err: io::Error) -> Self {
+        EditorError::Terminal(err)
+    }
+}
@@ -0,0 +1,399 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+    Quit,
+    Save,
+    Find,
+    Replace,
+    Undo,
+    Redo,
+    ToggleWrapMode,
+    ToggleCentered,
+    SetTextWidth,
+    SetWrapColumn,
+    SetEncoding,
+    SetLineEnding,
+    ToggleFinalNewline,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    JumpUp,
+    JumpDown,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    DeleteBack,
+    DeleteForward,
+    DeleteWordBack,
+    DeleteWordForward,
+    Newline,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectLineStart,
+    SelectLineEnd,
+    SelectPageUp,
+    SelectPageDown,
+    SelectAll,
+    Copy,
+    Cut,
+    Paste,
+    OpenFile,
+    NextBuffer,
+    PrevBuffer,
+    ListBuffers,
+    SplitHorizontal,
+    SplitVertical,
+    ClosePane,
+    NextPane,
+    PrevPane,
+    GrowPane,
+    ShrinkPane,
+    Palette
+}
+
+const COMMANDS: &[(Command, &str, &str, &[&str])] = &[ // (command, name, description, default bindings)
+    (Command::Quit, "quit", "Quit", &["ctrl+c"]),
+    (Command::Save, "save", "Save file", &["ctrl+s", "alt+s"]),
+    (Command::Find, "find", "Find", &["ctrl+f"]),
+    (Command::Replace, "replace", "Find and replace (regex)", &["ctrl+r"]),
+    (Command::Undo, "undo", "Undo", &["ctrl+z"]),
+    (Command::Redo, "redo", "Redo", &["ctrl+y"]),
+    (Command::ToggleWrapMode, "toggle_wrap_mode", "Toggle soft / hard wrapping", &["alt+w"]),
+    (Command::ToggleCentered, "toggle_centered", "Toggle centered (zen) layout", &["alt+z"]),
+    (Command::SetTextWidth, "set_text_width", "Set text width", &["alt+l"]),
+    (Command::SetWrapColumn, "set_wrap_column", "Set wrap column", &["alt+k"]),
+    (Command::SetEncoding, "set_encoding", "Convert file encoding", &[]),
+    (Command::SetLineEnding, "set_line_ending", "Convert line endings", &[]),
+    (Command::ToggleFinalNewline, "toggle_final_newline", "Toggle newline at end of file", &[]),
+    (Command::CursorUp, "cursor_up", "Cursor up", &["up"]),
+    (Command::CursorDown, "cursor_down", "Cursor down", &["down"]),
+    (Command::CursorLeft, "cursor_left", "Cursor left", &["left"]),
+    (Command::CursorRight, "cursor_right", "Cursor right", &["right"]),
+    (Command::JumpUp, "jump_up", "Jump five lines up", &["ctrl+up"]),
+    (Command::JumpDown, "jump_down", "Jump five lines down", &["ctrl+down"]),
+    (Command::WordLeft, "word_left", "Previous word", &["ctrl+left"]),
+    (Command::WordRight, "word_right", "Next word", &["ctrl+right"]),
+    (Command::LineStart, "line_start", "Start of line", &["home"]),
+    (Command::LineEnd, "line_end", "End of line", &["end"]),
+    (Command::PageUp, "page_up", "Page up", &["pageup"]),
+    (Command::PageDown, "page_down", "Page down", &["pagedown"]),
+    (Command::DeleteBack, "delete_back", "Delete previous character", &["backspace"]),
+    (Command::DeleteForward, "delete_forward", "Delete next character", &["delete"]),
+    (Command::DeleteWordBack, "delete_word_back", "Delete previous word", &["shift+backspace"]),
+    (Command::DeleteWordForward, "delete_word_forward", "Delete next word", &["shift+delete"]),
+    (Command::Newline, "newline", "Insert line break", &["enter"]),
+    (Command::SelectUp, "select_up", "Extend selection up", &["shift+up"]),
+    (Command::SelectDown, "select_down", "Extend selection down", &["shift+down"]),
+    (Command::SelectLeft, "select_left", "Extend selection left", &["shift+left"]),
+    (Command::SelectRight, "select_right", "Extend selection right", &["shift+right"]),
+    (Command::SelectWordLeft, "select_word_left", "Extend selection to previous word", &["ctrl+shift+left"]),
+    (Command::SelectWordRight, "select_word_right", "Extend selection to next word", &["ctrl+shift+right"]),
+    (Command::SelectLineStart, "select_line_start", "Extend selection to start of line", &["shift+home"]),
+    (Command::SelectLineEnd, "select_line_end", "Extend selection to end of line", &["shift+end"]),
+    (Command::SelectPageUp, "select_page_up", "Extend selection a page up", &["shift+pageup"]),
+    (Command::SelectPageDown, "select_page_down", "Extend selection a page down", &["shift+pagedown"]),
+    (Command::SelectAll, "select_all", "Select all", &["ctrl+a"]),
+    (Command::Copy, "copy", "Copy selection", &["ctrl+k c", "alt+c", "ctrl+insert"]), // Alt+C types ç on macOS layouts
+    (Command::Cut, "cut", "Cut selection", &["ctrl+x"]),
+    (Command::Paste, "paste", "Paste", &["ctrl+v", "shift+insert"]),
+    (Command::OpenFile, "open_file", "Open file", &["ctrl+o"]),
+    (Command::NextBuffer, "next_buffer", "Next buffer", &["ctrl+pagedown", "alt+n"]),
+    (Command::PrevBuffer, "prev_buffer", "Previous buffer", &["ctrl+pageup", "alt+p"]),
+    (Command::ListBuffers, "list_buffers", "Switch buffer", &["alt+b"]),
+    (Command::SplitHorizontal, "split_horizontal", "Split pane, new pane below", &["ctrl+w s"]),
+    (Command::SplitVertical, "split_vertical", "Split pane, new pane to the right", &["ctrl+w v"]),
+    (Command::ClosePane, "close_pane", "Close pane", &["ctrl+w q", "ctrl+w c"]),
+    (Command::NextPane, "next_pane", "Focus next pane", &["ctrl+w w", "ctrl+w ctrl+w"]),
+    (Command::PrevPane, "prev_pane", "Focus previous pane", &["ctrl+w p"]),
+    (Command::GrowPane, "grow_pane", "Make pane larger", &["ctrl+w +", "ctrl+w ="]),
+    (Command::ShrinkPane, "shrink_pane", "Make pane smaller", &["ctrl+w -"]),
+    (Command::Palette, "command_palette", "Command palette", &["ctrl+p", "ctrl+shift+p"])
+];
+
+impl Command {
+    pub fn all() -> impl Iterator<Item = Command> {
+        COMMANDS.iter().map(|(cmd, ..)| *cmd)
+    }
+
+    pub fn name(&self) -> &'static str {
+        COMMANDS.iter().find(|(cmd, ..)| cmd == self).map_or("", |(_, name, ..)| name)
+    }
+
+    pub fn edits(&self) -> bool { // not allowed in read-only mode
+        matches!(
+            self,
+            Command::Save
+                | Command::Replace
+                | Command::Undo
+                | Command::Redo
+                | Command::SetEncoding
+                | Command::SetLineEnding
+                | Command::ToggleFinalNewline
+                | Command::DeleteBack
+                | Command::DeleteForward
+                | Command::DeleteWordBack
+                | Command::DeleteWordForward
+                | Command::Newline
+                | Command::Cut
+                | Command::Paste
+        )
+    }
+
+    pub fn description(&self) -> &'static str {
+        COMMANDS.iter().find(|(cmd, ..)| cmd == self).map_or("", |(_, _, description, _)| description)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        COMMANDS.iter().find(|(_, it, ..)| *it == name).map(|(cmd, ..)| *cmd)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(event: KeyEvent) -> Self {
+        let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = event.code {
+            modifiers -= KeyModifiers::SHIFT // already part of the character
+        }
+        Self { code: event.code, modifiers }
+    }
+}
+
+impl KeyChord {
+    fn parse(chord: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = chord.split('+').collect();
+        let key = match parts.pop() {
+            Some("") if chord.ends_with("++") || chord == "+" => { // the `+` key itself
+                parts.pop();
+                "+"
+            }
+            Some(key) => key,
+            None => return Err(format!("empty key in '{}'", chord))
+        };
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "option" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", part, chord))
+            }
+        }
+        let code = match key.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "space" => KeyCode::Char(' '),
+            lower => match (lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()), key.chars().count()) {
+                (Some(n), _) if (1..=12).contains(&n) => KeyCode::F(n),
+                (_, 1) => {
+                    let ch = key.chars().next().unwrap();
+                    if modifiers.contains(KeyModifiers::SHIFT) {
+                        modifiers -= KeyModifiers::SHIFT;
+                        KeyCode::Char(ch.to_uppercase().next().unwrap_or(ch))
+                    } else {
+                        KeyCode::Char(ch)
+                    }
+                }
+                _ => return Err(format!("unknown key '{}' in '{}'", key, chord))
+            }
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+")
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(ch) if self.modifiers.is_empty() => write!(f, "{}", ch),
+            KeyCode::Char(ch) => write!(f, "{}", ch.to_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            code => write!(f, "{:?}", code)
+        }
+    }
+}
+
+pub enum Lookup {
+    Command(Command),
+    Pending, // the keys so far start a longer binding
+    Unbound
+}
+
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyChord>, Command)>
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&[]).0
+    }
+}
+
+impl Keymap {
+    /// Builds the default bindings with `overrides` applied, where each override
+    /// replaces every binding of its command and takes its keys from any default
+    /// binding that has them. Returns any problems found.
+    pub fn new(overrides: &[(String, Vec<String>)]) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut defaults: Vec<(Vec<KeyChord>, Command)> = COMMANDS
+            .iter()
+            .flat_map(|(cmd, _, _, keys)| keys.iter().map(|keys| (Self::parse_sequence(keys).unwrap(), *cmd)))
+            .collect();
+        let mut bindings = Vec::new(); // overrides go first, so they win in `lookup`
+        for (name, keys) in overrides {
+            let Some(cmd) = Command::from_name(name) else {
+                errors.push(format!("unknown command '{}' in [keys]", name));
+                continue
+            };
+            let parsed: Result<Vec<_>, _> = keys.iter().map(|keys| Self::parse_sequence(keys)).collect();
+            match parsed {
+                Ok(sequences) => {
+                    bindings.retain(|(_, it)| *it != cmd);
+                    defaults.retain(|(keys, _)| !sequences.iter().any(|it| Self::overlap(keys, it)));
+                    bindings.extend(sequences.into_iter().map(|keys| (keys, cmd)));
+                }
+                Err(err) => errors.push(format!("binding for '{}': {}", name, err))
+            }
+        }
+        defaults.retain(|(_, cmd)| !bindings.iter().any(|(_, it)| it == cmd));
+        bindings.extend(defaults);
+        let keymap = Self { bindings };
+        errors.extend(keymap.conflicts());
+        (keymap, errors)
+    }
+
+    fn parse_sequence(keys: &str) -> Result<Vec<KeyChord>, String> {
+        let chords: Result<Vec<_>, _> = keys.split_whitespace().map(KeyChord::parse).collect();
+        chords.and_then(|chords| if chords.is_empty() { Err("empty binding".into()) } else { Ok(chords) })
+    }
+
+    fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (idx, (keys, cmd)) in self.bindings.iter().enumerate() {
+            for (other_keys, other) in &self.bindings[idx + 1..] {
+                if Self::overlap(keys, other_keys) && (cmd != other || keys.len() != other_keys.len()) {
+                    conflicts.push(format!(
+                        "{} ({}) conflicts with {} ({})",
+                        Self::describe(keys), cmd.name(), Self::describe(other_keys), other.name()
+                    ))
+                }
+            }
+        }
+        conflicts
+    }
+
+    fn overlap(keys: &[KeyChord], other: &[KeyChord]) -> bool { // equal, or one is a prefix of the other
+        let shorter = keys.len().min(other.len());
+        keys[..shorter] == other[..shorter]
+    }
+
+    pub fn lookup(&self, keys: &[KeyChord]) -> Lookup {
+        let mut pending = false;
+        for (binding, cmd) in &self.bindings {
+            if binding.as_slice() == keys {
+                return Lookup::Command(*cmd)
+            }
+            pending |= binding.starts_with(keys);
+        }
+        if pending { Lookup::Pending } else { Lookup::Unbound }
+    }
+
+    pub fn bindings_for(&self, command: Command) -> String {
+        self.bindings
+            .iter()
+            .filter(|(_, cmd)| *cmd == command)
+            .map(|(keys, _)| Self::describe(keys))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn describe(keys: &[KeyChord]) -> String {
+        keys.iter().map(|it| it.to_string()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(keymap: &Keymap, keys: &str) -> Option<Command> {
+        match keymap.lookup(&Keymap::parse_sequence(keys).unwrap()) {
+            Lookup::Command(cmd) => Some(cmd),
+            _ => None
+        }
+    }
+
+    fn overrides(pairs: &[(&str, &str)]) -> Vec<(String, Vec<String>)> {
+        pairs.iter().map(|(name, keys)| (name.to_string(), vec![keys.to_string()])).collect()
+    }
+
+    #[test]
+    fn overrides_take_keys_from_defaults() {
+        let (keymap, errors) = Keymap::new(&overrides(&[("find", "ctrl+s")]));
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(command(&keymap, "ctrl+s") == Some(Command::Find));
+        assert!(command(&keymap, "alt+s") == Some(Command::Save));
+        assert!(command(&keymap, "ctrl+f").is_none());
+
+        let (keymap, errors) = Keymap::new(&overrides(&[("quit", "ctrl+w")])); // a prefix of the pane bindings
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(command(&keymap, "ctrl+w") == Some(Command::Quit));
+        assert_eq!(keymap.bindings_for(Command::SplitVertical), "");
+    }
+
+    #[test]
+    fn defaults_leave_typing_alone() {
+        let (keymap, errors) = Keymap::new(&[]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        for (keys, cmd) in &keymap.bindings {
+            let typed = matches!(keys[0].code, KeyCode::Char(_)) && (keys[0].modifiers - KeyModifiers::SHIFT).is_empty();
+            assert!(!typed, "{} ({}) would stop that character from being typed", Keymap::describe(keys), cmd.name());
+        }
+        assert!(command(&keymap, "ctrl+k c") == Some(Command::Copy));
+    }
+
+    #[test]
+    fn conflicting_overrides_are_reported() {
+        let (_, errors) = Keymap::new(&overrides(&[("find", "ctrl+k"), ("save", "ctrl+k s")]));
+        assert_eq!(errors.len(), 1);
+    }
+}
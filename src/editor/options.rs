@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+/// What to open and how, as given on the command line.
+#[derive(Default)]
+pub struct Options {
+    pub files: Vec<FileArg>, // an empty buffer is opened when there are none
+    pub readonly: bool,
+    pub config: Option<PathBuf> // replaces the user and project config files
+}
+
+pub struct FileArg {
+    pub source: Source,
+    pub position: Option<(usize, usize)> // 1-based line and column to put the cursor on
+}
+
+pub enum Source {
+    Path(PathBuf),
+    Stdin(Vec<u8>) // read before the terminal is taken over, opens as an unnamed buffer
+}
@@ -1,28 +1,202 @@
 pub mod editor;
 
 use editor::Editor;
-use crossterm::{execute, cursor, terminal};
+use editor::error::Result;
+use editor::options::{FileArg, Options, Source};
+use crossterm::{event, execute, cursor, terminal};
+use std::io::Read;
+use std::{env, io, process};
+
+const USAGE: &str = "\
+Usage: text-magic [OPTIONS] [+LINE[:COL]] [FILE]...
+
+Opens every FILE in its own buffer, or an empty buffer when there are none.
+
+  +LINE[:COL]          put the cursor on LINE (and COL) of the file after it
+  -                    read a buffer from standard input
+  -R, --readonly       open everything read-only
+  -c, --config PATH    use this config file instead of the usual ones
+  -h, --help           show this help
+  -V, --version        show the version
+  --                   treat the remaining arguments as file names";
+
+enum Action {
+    Edit(Options),
+    Help,
+    Version
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Action, String> {
+    let mut options = Options::default();
+    let mut position = None; // for the next file
+    let mut options_ended = false;
+    while let Some(arg) = args.next() {
+        let source = match arg.as_str() {
+            _ if options_ended => Source::Path(arg.into()),
+            "-" if options.files.iter().any(|file| matches!(file.source, Source::Stdin(_))) => {
+                return Err("standard input can only be read once".into())
+            }
+            "-" => Source::Stdin(Vec::new()), // read once the arguments are known to be valid
+            "--" => {
+                options_ended = true;
+                continue
+            }
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            "-R" | "--readonly" => {
+                options.readonly = true;
+                continue
+            }
+            "-c" | "--config" => {
+                let path = args.next().ok_or_else(|| format!("{} needs a path", arg))?;
+                options.config = Some(path.into());
+                continue
+            }
+            _ if arg.starts_with("--config=") => {
+                options.config = Some(arg["--config=".len()..].into());
+                continue
+            }
+            _ if arg.starts_with('+') => {
+                position = Some(parse_position(&arg[1..]).ok_or_else(|| format!("expected +LINE[:COL], got '{}'", arg))?);
+                continue
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => Source::Path(arg.into())
+        };
+        options.files.push(FileArg { source, position: position.take() });
+    }
+    if position.is_some() {
+        return Err("+LINE[:COL] needs a file after it".into())
+    }
+    Ok(Action::Edit(options))
+}
+
+fn parse_position(position: &str) -> Option<(usize, usize)> {
+    let (line, column) = position.split_once(':').unwrap_or((position, "1"));
+    let line: usize = line.parse().ok()?;
+    let column: usize = column.parse().ok()?;
+    (line > 0 && column > 0).then_some((line, column))
+}
 
 struct CleanUp;
 
 impl Drop for CleanUp {
-    fn drop(&mut self) {
-        terminal::disable_raw_mode().expect("Failed to disable raw-mode for terminal");
-        execute!(
-            std::io::stdout(), 
-            terminal::Clear(terminal::ClearType::All)
-        ).expect("Error clearing the screen on exit");
-        execute!(
-            std::io::stdout(), 
-            cursor::MoveTo(0,0),
-        ).expect("Error resetting the cursor to (0,0) on exit");
+    fn drop(&mut self) { // best effort, there's nobody left to report failures to
+        let _ = execute!(
+            io::stdout(),
+            event::DisableBracketedPaste,
+            event::DisableMouseCapture,
+            event::DisableFocusChange
+        );
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0,0)
+        );
     }
 }
 
-fn main() -> crossterm::Result<()> {
+fn run(options: Options) -> Result<()> {
     let _clean_up = CleanUp;
     terminal::enable_raw_mode()?;
-    let mut editor = Editor::new();
+    execute!(
+        io::stdout(),
+        event::EnableBracketedPaste,
+        event::EnableMouseCapture,
+        event::EnableFocusChange
+    )?;
+    let mut editor = Editor::new(options);
     while editor.run()? {};
     Ok(())
 }
+
+fn main() {
+    let mut options = match parse_args(env::args().skip(1)) {
+        Ok(Action::Edit(options)) => options,
+        Ok(Action::Help) => return println!("{}", USAGE),
+        Ok(Action::Version) => return println!("text-magic {}", env!("CARGO_PKG_VERSION")),
+        Err(err) => {
+            eprintln!("text-magic: {}\n\n{}", err, USAGE);
+            process::exit(2)
+        }
+    };
+    for file in &mut options.files {
+        if let Source::Stdin(bytes) = &mut file.source {
+            if let Err(err) = io::stdin().read_to_end(bytes) {
+                eprintln!("text-magic: couldn't read standard input: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+    if let Err(err) = run(options) { // the terminal is restored and unsaved changes are in swap files by now
+        eprintln!("text-magic: {}", err);
+        process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn parse(args: &[&str]) -> std::result::Result<Action, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn edit_options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Action::Edit(options)) => options,
+            Ok(_) => panic!("{:?} didn't open the editor", args),
+            Err(err) => panic!("{:?} failed: {}", args, err)
+        }
+    }
+
+    fn paths(options: &Options) -> Vec<Option<PathBuf>> {
+        options.files.iter().map(|file| match &file.source {
+            Source::Path(path) => Some(path.clone()),
+            Source::Stdin(_) => None
+        }).collect()
+    }
+
+    #[test]
+    fn unknown_options() {
+        assert_eq!(parse(&["-x"]).err().as_deref(), Some("unknown option '-x'"));
+        assert_eq!(parse(&["a.txt", "--frobnicate"]).err().as_deref(), Some("unknown option '--frobnicate'"));
+        assert_eq!(parse(&["+3x", "a.txt"]).err().as_deref(), Some("expected +LINE[:COL], got '+3x'"));
+        assert!(matches!(parse(&["-h", "-x"]), Ok(Action::Help)));
+        assert!(matches!(parse(&["--version"]), Ok(Action::Version)));
+    }
+
+    #[test]
+    fn missing_option_values() {
+        assert_eq!(parse(&["-c"]).err().as_deref(), Some("-c needs a path"));
+        assert_eq!(parse(&["a.txt", "--config"]).err().as_deref(), Some("--config needs a path"));
+        assert_eq!(parse(&["a.txt", "+3"]).err().as_deref(), Some("+LINE[:COL] needs a file after it"));
+        let options = edit_options(&["-c", "-R", "--config=b.toml", "a.txt"]); // the path is taken as is, the last one wins
+        assert_eq!(options.config, Some(PathBuf::from("b.toml")));
+        assert!(!options.readonly);
+        assert_eq!(paths(&options), [Some("a.txt".into())]);
+    }
+
+    #[test]
+    fn several_files_and_double_dash() {
+        let options = edit_options(&["-R", "a.txt", "+2:5", "b.txt", "--", "-R", "+3", "-", "--"]);
+        assert!(options.readonly);
+        assert_eq!(
+            paths(&options),
+            ["a.txt", "b.txt", "-R", "+3", "-", "--"].map(|path| Some(PathBuf::from(path)))
+        );
+        let positions: Vec<_> = options.files.iter().map(|file| file.position).collect();
+        assert_eq!(positions, [None, Some((2, 5)), None, None, None, None]);
+        assert!(edit_options(&[]).files.is_empty());
+    }
+
+    #[test]
+    fn standard_input() {
+        let options = edit_options(&["a.txt", "+4", "-"]);
+        assert_eq!(paths(&options), [Some("a.txt".into()), None]);
+        assert_eq!(options.files[1].position, Some((4, 1)));
+        assert_eq!(parse(&["-", "a.txt", "-"]).err().as_deref(), Some("standard input can only be read once"));
+    }
+}